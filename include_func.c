@@ -27,4 +27,20 @@ int add5(int x, int y, int z, int a, int b) {
 int add6(int x, int y, int z, int a, int b, int c) { 
     printf("add result is %d\n", x+y+z+a+b+c); 
     return x+y+z+a+b+c;
-}
\ No newline at end of file
+}
+
+int add8(int x, int y, int z, int a, int b, int c, int d, int e) { 
+    printf("add result is %d\n", x+y+z+a+b+c+d+e); 
+    return x+y+z+a+b+c+d+e;
+}
+
+// 9個目の浮動小数点数の引数kはスタックで渡される
+double sum_mixed(int a, double b, float c, long d, double e, double f, double g, double h, double i, double j, double k) {
+    double sum = a + b + c + d + e + f + g + h + i + j + k;
+    printf("sum_mixed result is %f\n", sum);
+    return sum;
+}
+
+float half(float x) {
+    return x / 2;
+}
@@ -1,10 +1,17 @@
 use super::error::{error_exit, invalid_token_exit};
-use super::tokenizer::{BracesKind, OperationKind, ParenthesesKind, TokenList, PROGRAM_TEXT};
+use super::tokenizer::{
+    BracesKind, BracketsKind, OperationKind, ParenthesesKind, TokenList, PROGRAM_TEXT,
+};
+use super::types::{align_to, StructRef, Type};
 
 #[derive(PartialEq, Eq)]
 pub enum PrimaryNodeKind {
-    Number(i32),
-    LocalVariable(usize), // (offset from bsp)
+    Number(i64),
+    FloatNumber(u64),        // f64のビット列, floatかdoubleかはノードの型で決める
+    LocalVariable(usize),    // (offset from bsp)
+    GlobalVariable(String),  // ラベル名でRIP相対にアクセスする
+    StringLiteral(Vec<u8>),  // NUL終端を含まない文字列
+    FunctionAddress(String), // 式中の関数名, 値は関数のアドレス
 }
 
 #[derive(PartialEq, Eq)]
@@ -12,15 +19,31 @@ pub enum ASTNodeKind {
     Operation(OperationKind),
     Primary(PrimaryNodeKind),
     Assign(usize), // =の文字列中の位置 左辺値に誤りがある場合に渡せるようにする
+    CompoundAssign(OperationKind, usize), // a += b, ++a (演算, 演算子の文字列中の位置)
+    PostfixAssign(OperationKind, usize), // a++, a-- 演算前の値を返す
     Return,
     If,
     IfElse,
+    Conditional(usize), // cond ? a : b の?の文字列中の位置, bはvec[0]にある
     While,
+    DoWhile,
     For,
-    MultStmt, // Mult statement, 複文
+    Switch(Vec<i64>, bool), // (caseの値を出現順に並べたもの, defaultがあるか)
+    Case(usize),            // switch内で何番目のcaseか
+    Default,
+    MultStmt,        // Mult statement, 複文
+    Break(usize),    // breakの文字列中の位置 ループの外で使われた場合にエラーにする
+    Continue(usize), // continueの文字列中の位置
+    Goto(String),    // 飛び先のラベル名
+    Label(String),
+    ExprStmt, // 式文, 評価した値は捨てる
     FunctionCall(String),
-    Reference(usize), // &の文字列中の位置 右辺が変数でない場合にエラーにする
+    FunctionPointerCall, // 呼び出す関数のアドレスはleftに持つ
+    Reference(usize),    // &の文字列中の位置 右辺が変数でない場合にエラーにする
     Dereference(usize),
+    Member(usize), // 構造体先頭からのメンバのオフセット
+    Cast,          // 変換後の型はtyに持つ
+    Negation,      // 浮動小数点数の符号反転, 整数の-xは0 - xとして扱う
 }
 
 pub struct ASTNode {
@@ -28,15 +51,17 @@ pub struct ASTNode {
     pub left: Option<Box<ASTNode>>,
     pub right: Option<Box<ASTNode>>,
     pub vec: Option<Vec<Option<Box<ASTNode>>>>,
+    pub ty: Option<Type>, // 式の型, 文の場合はNone
 }
 
 impl ASTNode {
-    fn new_primary_node(primary_node: PrimaryNodeKind) -> ASTNode {
+    fn new_primary_node(primary_node: PrimaryNodeKind, ty: Type) -> ASTNode {
         ASTNode {
             node_kind: ASTNodeKind::Primary(primary_node),
             left: None,
             right: None,
             vec: None,
+            ty: Some(ty),
         }
     }
 
@@ -46,6 +71,7 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
         }
     }
 
@@ -55,6 +81,17 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_member_node(offset: usize, ty: Type) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Member(offset),
+            left: None,
+            right: None,
+            vec: None,
+            ty: Some(ty),
         }
     }
 
@@ -64,6 +101,7 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
         }
     }
 
@@ -73,6 +111,27 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_compound_assign_node(operation: OperationKind, node_pos: usize) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::CompoundAssign(operation, node_pos),
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_postfix_assign_node(operation: OperationKind, node_pos: usize) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::PostfixAssign(operation, node_pos),
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
         }
     }
 
@@ -82,6 +141,7 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
         }
     }
 
@@ -91,6 +151,17 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_conditional_node(node_pos: usize) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Conditional(node_pos),
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
         }
     }
 
@@ -100,6 +171,47 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_do_while_node() -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::DoWhile,
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_switch_node(case_values: Vec<i64>, has_default: bool) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Switch(case_values, has_default),
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_case_node(case_index: usize) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Case(case_index),
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_default_node() -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Default,
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
         }
     }
 
@@ -109,6 +221,57 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_expr_stmt_node() -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::ExprStmt,
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_break_node(node_pos: usize) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Break(node_pos),
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_continue_node(node_pos: usize) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Continue(node_pos),
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_goto_node(label_name: String) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Goto(label_name),
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_label_node(label_name: String) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Label(label_name),
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
         }
     }
 
@@ -118,6 +281,27 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_cast_node(ty: Type) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Cast,
+            left: None,
+            right: None,
+            vec: None,
+            ty: Some(ty),
+        }
+    }
+
+    fn new_negation_node(ty: Type) -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::Negation,
+            left: None,
+            right: None,
+            vec: None,
+            ty: Some(ty),
         }
     }
 
@@ -127,6 +311,17 @@ impl ASTNode {
             left: None,
             right: None,
             vec: None,
+            ty: None,
+        }
+    }
+
+    fn new_function_pointer_call_node() -> ASTNode {
+        ASTNode {
+            node_kind: ASTNodeKind::FunctionPointerCall,
+            left: None,
+            right: None,
+            vec: None,
+            ty: None,
         }
     }
 
@@ -135,101 +330,578 @@ impl ASTNode {
         self.left = left;
         self.right = right;
     }
+
+    // 定数式であれば値を計算する, 変数や関数呼び出しを含む場合はNone
+    fn eval_constant(&self) -> Option<i64> {
+        if let ASTNodeKind::Primary(PrimaryNodeKind::Number(num)) = self.node_kind {
+            return Some(num);
+        }
+        let left = self.left.as_ref()?.eval_constant()?;
+        let value = match &self.node_kind {
+            ASTNodeKind::Conditional(_) => {
+                if left != 0 {
+                    self.right.as_ref()?.eval_constant()?
+                } else {
+                    self.vec.as_ref()?[0].as_ref()?.eval_constant()?
+                }
+            }
+            ASTNodeKind::Cast if self.ty.as_ref()?.is_integer() => {
+                self.ty.as_ref()?.convert_constant(left)
+            }
+            ASTNodeKind::Operation(OperationKind::LogicalNot) => (left == 0) as i64,
+            ASTNodeKind::Operation(OperationKind::BitNot) => !left,
+            ASTNodeKind::Operation(OperationKind::LogicalAnd) if left == 0 => 0,
+            ASTNodeKind::Operation(OperationKind::LogicalOr) if left != 0 => 1,
+            ASTNodeKind::Operation(operation) => {
+                let right = self.right.as_ref()?.eval_constant()?;
+                // 両辺はset_typeで揃えてあるので, コンパイル時と同じく両辺の型で符号の有無を決める
+                // シフトは左辺の型だけで決まる
+                let left_ty = self.left.as_ref()?.ty.as_ref()?;
+                let right_ty = self.right.as_ref()?.ty.as_ref()?;
+                let is_unsigned = Type::common_type(left_ty, right_ty).is_unsigned();
+                let (left_u, right_u) = (left as u64, right as u64);
+                match operation {
+                    OperationKind::Add => left.wrapping_add(right),
+                    OperationKind::Sub => left.wrapping_sub(right),
+                    OperationKind::Mul => left.wrapping_mul(right),
+                    OperationKind::Div if is_unsigned => left_u.checked_div(right_u)? as i64,
+                    OperationKind::Div => left.checked_div(right)?,
+                    OperationKind::Mod if is_unsigned => left_u.checked_rem(right_u)? as i64,
+                    OperationKind::Mod => left.checked_rem(right)?,
+                    OperationKind::BitAnd => left & right,
+                    OperationKind::BitOr => left | right,
+                    OperationKind::BitXor => left ^ right,
+                    OperationKind::Shl => left.wrapping_shl(right as u32),
+                    OperationKind::Shr if left_ty.is_unsigned() => {
+                        left_u.wrapping_shr(right as u32) as i64
+                    }
+                    OperationKind::Shr => left.wrapping_shr(right as u32),
+                    OperationKind::Eq => (left == right) as i64,
+                    OperationKind::Not => (left != right) as i64,
+                    OperationKind::Lt if is_unsigned => (left_u < right_u) as i64,
+                    OperationKind::Lt => (left < right) as i64,
+                    OperationKind::Le if is_unsigned => (left_u <= right_u) as i64,
+                    OperationKind::Le => (left <= right) as i64,
+                    OperationKind::LogicalAnd | OperationKind::LogicalOr => (right != 0) as i64,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        // 演算結果は実行時と同じく, ノードの型に合わせて切り詰めて拡張する
+        match self.ty.as_ref() {
+            Some(ty) => Some(ty.convert_constant(value)),
+            None => Some(value),
+        }
+    }
+
+    // 子ノードの型からノードの型を決める
+    // 型が演算に合わない場合はnode_posにエラーを立てる
+    fn set_type(&mut self, node_pos: usize) {
+        let left_ty = self.left.as_ref().and_then(|node| node.ty.clone());
+        let right_ty = self.right.as_ref().and_then(|node| node.ty.clone());
+        // void関数の呼び出し結果は値として使えない
+        if left_ty == Some(Type::Void) || right_ty == Some(Type::Void) {
+            error_exit("void value is not ignored", node_pos);
+        }
+        if let ASTNodeKind::Operation(_)
+        | ASTNodeKind::CompoundAssign(_, _)
+        | ASTNodeKind::PostfixAssign(_, _) = self.node_kind
+        {
+            if left_ty.as_ref().is_some_and(|ty| ty.is_struct())
+                || right_ty.as_ref().is_some_and(|ty| ty.is_struct())
+            {
+                error_exit("invalid operand for struct", node_pos);
+            }
+            // 剰余, ビット演算, シフトは整数だけに使える
+            if let ASTNodeKind::Operation(operation) | ASTNodeKind::CompoundAssign(operation, _) =
+                &self.node_kind
+            {
+                let is_integer_operation = matches!(
+                    operation,
+                    OperationKind::Mod
+                        | OperationKind::BitAnd
+                        | OperationKind::BitOr
+                        | OperationKind::BitXor
+                        | OperationKind::Shl
+                        | OperationKind::Shr
+                        | OperationKind::BitNot
+                );
+                if is_integer_operation
+                    && (left_ty.as_ref().is_some_and(|ty| ty.is_float())
+                        || right_ty.as_ref().is_some_and(|ty| ty.is_float()))
+                {
+                    error_exit("invalid operand for floating point", node_pos);
+                }
+            }
+        }
+        // ポインタと浮動小数点数は演算や代入で混ぜられない
+        if let (Some(left_ty), Some(right_ty)) = (&left_ty, &right_ty) {
+            if (left_ty.is_pointer() && right_ty.is_float())
+                || (left_ty.is_float() && right_ty.is_pointer())
+            {
+                error_exit("invalid operand for pointer", node_pos);
+            }
+        }
+        let ty = match &self.node_kind {
+            ASTNodeKind::Operation(OperationKind::Add) => {
+                let (left_ty, right_ty) = (left_ty.unwrap(), right_ty.unwrap());
+                if left_ty.is_pointer() && right_ty.is_pointer() {
+                    error_exit("cannot add pointer to pointer", node_pos);
+                } else if let Some(pointee) = left_ty.pointee() {
+                    // 配列はポインタとして扱う
+                    Type::pointer_to(pointee.clone())
+                } else if let Some(pointee) = right_ty.pointee() {
+                    Type::pointer_to(pointee.clone())
+                } else {
+                    Type::common_type(&left_ty, &right_ty)
+                }
+            }
+            ASTNodeKind::Operation(OperationKind::Sub) => {
+                let (left_ty, right_ty) = (left_ty.unwrap(), right_ty.unwrap());
+                if left_ty.is_pointer() && right_ty.is_pointer() {
+                    // ポインタ同士の差は要素数になる
+                    Type::Long
+                } else if let Some(pointee) = left_ty.pointee() {
+                    Type::pointer_to(pointee.clone())
+                } else if right_ty.is_pointer() {
+                    error_exit("cannot subtract pointer from integer", node_pos);
+                } else {
+                    Type::common_type(&left_ty, &right_ty)
+                }
+            }
+            ASTNodeKind::Operation(OperationKind::Mul)
+            | ASTNodeKind::Operation(OperationKind::Div)
+            | ASTNodeKind::Operation(OperationKind::Mod)
+            | ASTNodeKind::Operation(OperationKind::BitAnd)
+            | ASTNodeKind::Operation(OperationKind::BitOr)
+            | ASTNodeKind::Operation(OperationKind::BitXor) => {
+                let (left_ty, right_ty) = (left_ty.unwrap(), right_ty.unwrap());
+                if left_ty.is_pointer() || right_ty.is_pointer() {
+                    error_exit("invalid operand for pointer", node_pos);
+                }
+                Type::common_type(&left_ty, &right_ty)
+            }
+            // シフトの結果は左辺の型になる
+            ASTNodeKind::Operation(OperationKind::Shl)
+            | ASTNodeKind::Operation(OperationKind::Shr) => {
+                let (left_ty, right_ty) = (left_ty.unwrap(), right_ty.unwrap());
+                if left_ty.is_pointer() || right_ty.is_pointer() {
+                    error_exit("invalid operand for pointer", node_pos);
+                }
+                left_ty.promoted()
+            }
+            ASTNodeKind::Operation(OperationKind::BitNot) => {
+                let left_ty = left_ty.unwrap();
+                if left_ty.is_pointer() {
+                    error_exit("invalid operand for pointer", node_pos);
+                }
+                left_ty.promoted()
+            }
+            ASTNodeKind::Assign(_) => {
+                let left_ty = left_ty.unwrap();
+                if left_ty.is_array() {
+                    error_exit("cannot assign to array", node_pos);
+                }
+                if left_ty.is_function() {
+                    error_exit("cannot assign to function", node_pos);
+                }
+                let right_ty = right_ty.unwrap();
+                if (left_ty.is_struct() || right_ty.is_struct()) && left_ty != right_ty {
+                    error_exit("incompatible struct assignment", node_pos);
+                }
+                left_ty
+            }
+            ASTNodeKind::CompoundAssign(operation, _)
+            | ASTNodeKind::PostfixAssign(operation, _) => {
+                let (left_ty, right_ty) = (left_ty.unwrap(), right_ty.unwrap());
+                if left_ty.is_array() {
+                    error_exit("cannot assign to array", node_pos);
+                }
+                // p += n のポインタ演算以外ではポインタを演算に使えない
+                let pointer_operation =
+                    *operation == OperationKind::Add || *operation == OperationKind::Sub;
+                if right_ty.is_pointer() || (left_ty.is_pointer() && !pointer_operation) {
+                    error_exit("invalid operand for pointer", node_pos);
+                }
+                left_ty
+            }
+            ASTNodeKind::Conditional(_) => {
+                let then_ty = right_ty.unwrap();
+                let else_ty = self.vec.as_ref().unwrap()[0].as_ref().unwrap().ty.clone();
+                let else_ty = else_ty.unwrap();
+                if (then_ty.is_struct() || else_ty.is_struct()) && then_ty != else_ty {
+                    error_exit("incompatible types in conditional expression", node_pos);
+                }
+                // 配列はポインタとして扱い, 片方だけがポインタの場合はポインタ型にする
+                if let Some(pointee) = then_ty.pointee() {
+                    Type::pointer_to(pointee.clone())
+                } else if let Some(pointee) = else_ty.pointee() {
+                    Type::pointer_to(pointee.clone())
+                } else if then_ty.is_arithmetic() && else_ty.is_arithmetic() {
+                    Type::common_type(&then_ty, &else_ty)
+                } else {
+                    then_ty
+                }
+            }
+            ASTNodeKind::Reference(_) => Type::pointer_to(left_ty.unwrap()),
+            ASTNodeKind::Dereference(_) => match left_ty.unwrap().pointee() {
+                Some(ty) => ty.clone(),
+                None => {
+                    error_exit("cannot dereference non pointer value", node_pos);
+                }
+            },
+            _ => Type::Int,
+        };
+        self.convert_operands(&ty);
+        self.ty = Some(ty);
+    }
+
+    // 演算の前に両辺の整数を演算する型へ変換する(通常の算術変換)
+    // tyはset_typeで決めたノードの型
+    fn convert_operands(&mut self, ty: &Type) {
+        let left_ty = self.left.as_ref().and_then(|node| node.ty.clone());
+        let right_ty = self.right.as_ref().and_then(|node| node.ty.clone());
+        match &self.node_kind {
+            ASTNodeKind::Operation(OperationKind::Eq)
+            | ASTNodeKind::Operation(OperationKind::Not)
+            | ASTNodeKind::Operation(OperationKind::Lt)
+            | ASTNodeKind::Operation(OperationKind::Le) => {
+                let common_ty = Type::common_type(&left_ty.unwrap(), &right_ty.unwrap());
+                self.left = convert_link(self.left.take(), &common_ty);
+                self.right = convert_link(self.right.take(), &common_ty);
+            }
+            // シフト量は左辺と別に整数拡張する
+            ASTNodeKind::Operation(OperationKind::Shl)
+            | ASTNodeKind::Operation(OperationKind::Shr) => {
+                self.left = convert_link(self.left.take(), ty);
+                self.right = convert_link(self.right.take(), &right_ty.unwrap().promoted());
+            }
+            ASTNodeKind::Operation(OperationKind::Add)
+            | ASTNodeKind::Operation(OperationKind::Sub)
+            | ASTNodeKind::Operation(OperationKind::Mul)
+            | ASTNodeKind::Operation(OperationKind::Div)
+            | ASTNodeKind::Operation(OperationKind::Mod)
+            | ASTNodeKind::Operation(OperationKind::BitAnd)
+            | ASTNodeKind::Operation(OperationKind::BitOr)
+            | ASTNodeKind::Operation(OperationKind::BitXor)
+            | ASTNodeKind::Operation(OperationKind::BitNot) => {
+                self.left = convert_link(self.left.take(), ty);
+                self.right = convert_link(self.right.take(), ty);
+            }
+            ASTNodeKind::Assign(_) => {
+                self.right = convert_link(self.right.take(), ty);
+            }
+            // 左辺は読み込んだ後にコンパイル時に変換するので, ここでは右辺だけを変換する
+            ASTNodeKind::CompoundAssign(operation, _)
+            | ASTNodeKind::PostfixAssign(operation, _)
+                if ty.is_arithmetic() =>
+            {
+                let right_ty = right_ty.unwrap();
+                let operation_ty = match operation {
+                    OperationKind::Shl | OperationKind::Shr => right_ty.promoted(),
+                    _ => Type::common_type(ty, &right_ty),
+                };
+                self.right = convert_link(self.right.take(), &operation_ty);
+            }
+            ASTNodeKind::Conditional(_) => {
+                self.right = convert_link(self.right.take(), ty);
+                let else_vec = self.vec.as_mut().unwrap();
+                else_vec[0] = convert_link(else_vec[0].take(), ty);
+            }
+            _ => {}
+        }
+    }
+}
+
+// 整数, 浮動小数点数の式を別の型に変換するノードで包む
+// 整数の数値はその場で変換し, 型が同じ場合や数値の型でない場合はそのまま返す
+fn convert_link(link: Link, ty: &Type) -> Link {
+    let mut node = link?;
+    let node_ty = match &node.ty {
+        Some(node_ty) if node_ty.is_arithmetic() && ty.is_arithmetic() && node_ty != ty => {
+            node_ty.clone()
+        }
+        _ => return Some(node),
+    };
+    if let ASTNodeKind::Primary(PrimaryNodeKind::Number(num)) = node.node_kind {
+        let primary_node = if ty.is_float() {
+            // 符号なし64bitの値は負のi64になっているので, u64として変換する
+            let value = if node_ty == Type::ULong {
+                num as u64 as f64
+            } else {
+                num as f64
+            };
+            PrimaryNodeKind::FloatNumber(value.to_bits())
+        } else {
+            PrimaryNodeKind::Number(ty.convert_constant(num))
+        };
+        node.node_kind = ASTNodeKind::Primary(primary_node);
+        node.ty = Some(ty.clone());
+        return Some(node);
+    }
+    let mut cast_node = ASTNode::new_cast_node(ty.clone());
+    cast_node.add_neighbor_node(Some(node), None);
+    Some(Box::new(cast_node))
 }
 
 /*
 AST 生成規則
-program    = stmt*
+program    = (function | global_variable)*
+function   = (integer_type | "float" | "double" | "void") ident "(" ("void" | type_name ident ("," type_name ident)*)? ")" ("{" stmt* "}" | ";")
+global_variable = type_name ident ("[" num "]")* ("=" "-"? (num | float_num))? ";"
+type_name  = ("void" | integer_type | "float" | "double" | struct_decl) "*"*
+integer_type = ("signed" | "unsigned")? ("char" | "short" "int"? | "int" | "long" "long"? "int"?)
+            (型指定子の順序は問わない, "unsigned"や"signed"だけの場合はint)
+struct_decl = "struct" ident? ("{" (type_name ident ("[" num "]")* ";")* "}")?
 stmt       = expr ";"
-            | stmt    = expr ";"
+            | declaration
             | "if" "(" expr ")" stmt ("else" stmt)?
             | "while" "(" expr ")" stmt
-            | "for" "(" expr? ";" expr? ";" expr? ")" stmt
-            |"return" expr ";"
+            | "do" stmt "while" "(" expr ")" ";"
+            | "for" "(" (declaration | expr? ";") expr? ";" expr? ")" stmt
+            | "switch" "(" expr ")" stmt
+            | "case" const_expr ":" stmt
+            | "default" ":" stmt
+            | "break" ";"
+            | "continue" ";"
+            | "goto" ident ";"
+            | ident ":" stmt
+            | "return" expr? ";"
             | "{" stmt* "}"
+declaration = type_name (ident ("[" num "]")* ("=" assign)?)? ";"
 expr       = assign
-assign     = equality ("=" assign)?
+assign     = conditional (("=" | "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "|=" | "^=" | "<<=" | ">>=") assign)?
+conditional = logor ("?" expr ":" conditional)?
+const_expr = conditional (値がコンパイル時に決まるもの)
+logor      = logand ("||" logand)*
+logand     = bitor ("&&" bitor)*
+bitor      = bitxor ("|" bitxor)*
+bitxor     = bitand ("^" bitand)*
+bitand     = equality ("&" equality)*
 equality   = relational ("==" relational | "!=" relational)*
-relational = add ("<" add | "<=" add | ">" add | ">=" add)*
+relational = shift ("<" shift | "<=" shift | ">" shift | ">=" shift)*
+shift      = add ("<<" add | ">>" add)*
 add        = mul ("+" mul | "-" mul)*
-mul        = unary ("*" unary | "/" unary)*
-unary      = ("+" | "-")? primary
-primary    = num | | ident ("(" ")")? | "(" expr ")"
+mul        = unary ("*" unary | "/" unary | "%" unary)*
+unary      = ("+" | "-")? postfix
+            | "*" unary
+            | "&" unary
+            | "!" unary
+            | "~" unary
+            | ("++" | "--") unary
+            | "sizeof" "(" type_name ")"
+            | "sizeof" unary
+            | "(" type_name ")" unary
+postfix    = primary ("[" expr "]" | "." ident | "->" ident | "++" | "--")*
+primary    = num | float_num | str | ident | ident "(" (assign ("," assign)*)? ")" | "(" expr ")"
 */
 
 type Link = Option<Box<ASTNode>>;
 // ;で区切られた領域のASTを作成する
+// 型名はコンパイラの用語どおり略語のままにする
+#[allow(clippy::upper_case_acronyms)]
 pub struct AST {
     pub root: Link,
 }
 
 impl AST {
-    pub fn new(token_list: &mut TokenList) -> AST {
-        let ast_tree = AST {
-            root: AST::stmt(token_list),
-        };
-        ast_tree
+    pub fn new(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> AST {
+        AST {
+            root: AST::stmt(token_list, function_info, program_info),
+        }
     }
 
     //stmt = expr ";"
-    //      | stmt    = expr ";"
+    //      | declaration
     //      | "if" "(" expr ")" stmt ("else" stmt)?
     //      | "while" "(" expr ")" stmt
-    //      | "for" "(" expr? ";" expr? ";" expr? ")" stmt
-    //      |"return" expr ";"
+    //      | "do" stmt "while" "(" expr ")" ";"
+    //      | "for" "(" (declaration | expr? ";") expr? ";" expr? ")" stmt
+    //      | "switch" "(" expr ")" stmt
+    //      | "case" const_expr ":" stmt
+    //      | "default" ":" stmt
+    //      | "break" ";"
+    //      | "continue" ";"
+    //      | "goto" ident ";"
+    //      | ident ":" stmt
+    //      | "return" expr? ";"
     //      | "{" stmt* "}"
-    fn stmt(token_list: &mut TokenList) -> Link {
+    fn stmt(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
         let stmt_link;
         if token_list.consume_return() {
             let mut return_node = ASTNode::new_return_node();
-            // 左辺値だけで良い
-            return_node.add_neighbor_node(AST::expr(token_list), None);
+            let is_void = function_info.return_ty == Type::Void;
+            if token_list.is_statement_end() {
+                if !is_void {
+                    invalid_token_exit("non-void function must return a value", token_list);
+                }
+            } else {
+                if is_void {
+                    invalid_token_exit("void function cannot return a value", token_list);
+                }
+                // 左辺値だけで良い
+                let value_node = AST::expr(token_list, function_info, program_info);
+                if value_node.as_ref().unwrap().ty == Some(Type::Void) {
+                    invalid_token_exit("void value is not ignored", token_list);
+                }
+                // 戻り値は関数の戻り値の型に変換する
+                return_node
+                    .add_neighbor_node(convert_link(value_node, &function_info.return_ty), None);
+            }
             stmt_link = Some(Box::new(return_node));
             token_list.consume_statement_end();
         } else if token_list.consume_if() {
-            stmt_link = AST::stmt_if(token_list);
+            stmt_link = AST::stmt_if(token_list, function_info, program_info);
         } else if token_list.consume_while() {
-            stmt_link = AST::stmt_while(token_list);
+            stmt_link = AST::stmt_while(token_list, function_info, program_info);
+        } else if token_list.consume_do() {
+            stmt_link = AST::stmt_do_while(token_list, function_info, program_info);
         } else if token_list.consume_for() {
-            stmt_link = AST::stmt_for(token_list);
+            stmt_link = AST::stmt_for(token_list, function_info, program_info);
+        } else if token_list.consume_switch() {
+            stmt_link = AST::stmt_switch(token_list, function_info, program_info);
+        } else if token_list.is_case() {
+            stmt_link = AST::stmt_case(token_list, function_info, program_info);
+        } else if token_list.is_default() {
+            let default_token = token_list.pop_head().unwrap();
+            let switch_info = match function_info.switch_infos.last_mut() {
+                Some(switch_info) => switch_info,
+                None => error_exit("default is not in switch", default_token.token_pos),
+            };
+            if switch_info.has_default {
+                error_exit("duplicate default label", default_token.token_pos);
+            }
+            switch_info.has_default = true;
+            if !token_list.consume_colon() {
+                invalid_token_exit("default requires ':'", token_list);
+            }
+            let mut default_node = ASTNode::new_default_node();
+            default_node
+                .add_neighbor_node(AST::stmt(token_list, function_info, program_info), None);
+            stmt_link = Some(Box::new(default_node));
+        } else if token_list.is_goto() {
+            // ラベルは後で定義されてもよいので, 関数全体を読んでから存在を確認する
+            token_list.pop_head();
+            let (label_name, label_pos) = token_list.expect_identifier();
+            function_info
+                .goto_labels
+                .push((label_name.clone(), label_pos));
+            stmt_link = Some(Box::new(ASTNode::new_goto_node(label_name)));
+            token_list.consume_statement_end();
+        } else if token_list.is_label() {
+            let (label_name, label_pos) = token_list.expect_identifier();
+            token_list.consume_colon();
+            function_info.add_label(label_name.clone(), label_pos);
+            let mut label_node = ASTNode::new_label_node(label_name);
+            label_node.add_neighbor_node(AST::stmt(token_list, function_info, program_info), None);
+            stmt_link = Some(Box::new(label_node));
+        } else if token_list.is_break() {
+            let break_token = token_list.pop_head().unwrap();
+            stmt_link = Some(Box::new(ASTNode::new_break_node(break_token.token_pos)));
+            token_list.consume_statement_end();
+        } else if token_list.is_continue() {
+            let continue_token = token_list.pop_head().unwrap();
+            stmt_link = Some(Box::new(ASTNode::new_continue_node(
+                continue_token.token_pos,
+            )));
+            token_list.consume_statement_end();
+        } else if token_list.is_type_name() {
+            stmt_link = AST::declaration(token_list, function_info, program_info);
         } else if token_list.comsume_braces(BracesKind::LeftBraces) {
-            // 複文の場合
+            // 複文の場合, {}の中で宣言した変数は}を抜けると見えなくなる
             let mut stmt_node = ASTNode::new_multstmt_node();
             let mut stmt_vec: Vec<Link> = vec![];
+            function_info.enter_scope();
             while !token_list.comsume_braces(BracesKind::RightBraces) {
-                stmt_vec.push(AST::stmt(token_list));
+                stmt_vec.push(AST::stmt(token_list, function_info, program_info));
             }
+            function_info.leave_scope();
             stmt_node.vec = Some(stmt_vec);
             stmt_link = Some(Box::new(stmt_node));
         } else {
-            stmt_link = AST::expr(token_list);
+            let mut expr_stmt_node = ASTNode::new_expr_stmt_node();
+            expr_stmt_node
+                .add_neighbor_node(AST::expr(token_list, function_info, program_info), None);
+            stmt_link = Some(Box::new(expr_stmt_node));
             token_list.consume_statement_end();
         }
 
         stmt_link
     }
 
-    fn stmt_if(token_list: &mut TokenList) -> Link {
+    // declaration = type_name (declarator ("=" assign)?)? ";"
+    // 初期値がある場合は代入の式文を返す, ない場合はスタックに割り当てるだけなのでノードは作らない
+    fn declaration(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let ty = pop_type_name(token_list, Some(&mut *function_info), program_info);
+        // struct point { ... }; のように構造体の型だけを宣言する場合
+        if ty.is_struct() && token_list.is_statement_end() {
+            token_list.consume_statement_end();
+            return None;
+        }
+        let (ty, identifier, identifier_pos) =
+            pop_variable_name(token_list, ty, Some(&mut *function_info), program_info);
+        let variable = function_info.add_local_variable(identifier, ty, identifier_pos);
+        if !token_list.is_assign() {
+            token_list.consume_statement_end();
+            return None;
+        }
+        let assign_token = token_list.pop_head().unwrap();
+        let variable_node =
+            ASTNode::new_primary_node(PrimaryNodeKind::LocalVariable(variable.offset), variable.ty);
+        let mut assign_node = ASTNode::new_assign_node(assign_token.token_pos);
+        assign_node.add_neighbor_node(
+            Some(Box::new(variable_node)),
+            AST::assign(token_list, function_info, program_info),
+        );
+        assign_node.set_type(assign_token.token_pos);
+        let mut expr_stmt_node = ASTNode::new_expr_stmt_node();
+        expr_stmt_node.add_neighbor_node(Some(Box::new(assign_node)), None);
+        token_list.consume_statement_end();
+        Some(Box::new(expr_stmt_node))
+    }
+
+    fn stmt_if(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
         // "if" を取り出し
         if token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
             // まずはelseのないif文としてASTnodeを作る
             let mut if_node = ASTNode::new_if_node();
-            if_node.add_neighbor_node(AST::expr(token_list), None);
+            if_node.add_neighbor_node(AST::expr(token_list, function_info, program_info), None);
             // ")"でクローズされているかチェック
             if token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
-                if_node.right = AST::stmt(token_list);
+                if_node.right = AST::stmt(token_list, function_info, program_info);
                 // else文が続く場合
                 if token_list.consume_else() {
                     if_node.node_kind = ASTNodeKind::IfElse;
-                    if_node.vec = Some(vec![AST::stmt_else(token_list)]);
+                    if_node.vec = Some(vec![AST::stmt_else(
+                        token_list,
+                        function_info,
+                        program_info,
+                    )]);
                 }
-                return Some(Box::new(if_node));
+                Some(Box::new(if_node))
             } else {
                 if let Some(valid_token) = token_list.pop_head() {
                     error_exit("if contition must be expression", valid_token.token_pos);
                 } else {
                     // テキスト終端に要求エラーを立てる
-                    let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
+                    let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
                     error_exit("parenthes is not closed", tail_pos);
                 }
             }
@@ -238,30 +910,38 @@ impl AST {
                 error_exit("if condition must start '(' ", valid_token.token_pos);
             } else {
                 // テキスト終端に要求エラーを立てる
-                let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
+                let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
                 error_exit("if condition is not given", tail_pos);
             }
         }
     }
 
-    fn stmt_else(token_list: &mut TokenList) -> Link {
-        AST::stmt(token_list)
+    fn stmt_else(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        AST::stmt(token_list, function_info, program_info)
     }
 
-    fn stmt_while(token_list: &mut TokenList) -> Link {
+    fn stmt_while(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
         if token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
             let mut while_node = ASTNode::new_while_node();
-            while_node.add_neighbor_node(AST::expr(token_list), None);
+            while_node.add_neighbor_node(AST::expr(token_list, function_info, program_info), None);
             // ")"でクローズされているかチェック
             if token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
-                while_node.right = AST::stmt(token_list);
-                return Some(Box::new(while_node));
+                while_node.right = AST::stmt(token_list, function_info, program_info);
+                Some(Box::new(while_node))
             } else {
                 if let Some(valid_token) = token_list.pop_head() {
                     error_exit("while contition must be expression", valid_token.token_pos);
                 } else {
                     // テキスト終端に要求エラーを立てる
-                    let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
+                    let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
                     error_exit("parenthes is not closed", tail_pos);
                 }
             }
@@ -270,22 +950,133 @@ impl AST {
                 error_exit("while condition must start '(' ", valid_token.token_pos);
             } else {
                 // テキスト終端に要求エラーを立てる
-                let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
+                let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
                 error_exit("while condition is not given", tail_pos);
             }
         }
     }
 
-    fn stmt_for(token_list: &mut TokenList) -> Link {
+    // "do" stmt "while" "(" expr ")" ";"
+    fn stmt_do_while(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut do_while_node = ASTNode::new_do_while_node();
+        let body_link = AST::stmt(token_list, function_info, program_info);
+        if !token_list.consume_while() {
+            invalid_token_exit("do statement requires while", token_list);
+        }
+        if !token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
+            invalid_token_exit("while condition must start '(' ", token_list);
+        }
+        let condition_link = AST::expr(token_list, function_info, program_info);
+        if !token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
+            invalid_token_exit("parenthes is not closed", token_list);
+        }
+        token_list.consume_statement_end();
+        do_while_node.add_neighbor_node(body_link, condition_link);
+        Some(Box::new(do_while_node))
+    }
+
+    // "switch" "(" expr ")" stmt
+    // 本体の中のcase, defaultはswitch_infosの末尾に登録される
+    fn stmt_switch(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        if !token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
+            invalid_token_exit("switch condition must start '(' ", token_list);
+        }
+        let condition_pos = token_list.peek_head().as_ref().map(|token| token.token_pos);
+        let condition_link = AST::expr(token_list, function_info, program_info);
+        if condition_link
+            .as_ref()
+            .is_some_and(|node| node.ty.as_ref().is_some_and(|ty| ty.is_float()))
+        {
+            error_exit("switch quantity is not an integer", condition_pos.unwrap());
+        }
+        if !token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
+            invalid_token_exit("parenthes is not closed", token_list);
+        }
+        // 条件式は整数拡張した型で比べ, caseの値もその型に変換する
+        let condition_ty = match condition_link.as_ref().and_then(|node| node.ty.as_ref()) {
+            Some(ty) => ty.promoted(),
+            None => Type::Int,
+        };
+        let condition_link = convert_link(condition_link, &condition_ty);
+        function_info.switch_infos.push(SwitchInfo {
+            case_values: vec![],
+            has_default: false,
+            condition_ty,
+        });
+        let body_link = AST::stmt(token_list, function_info, program_info);
+        let switch_info = function_info.switch_infos.pop().unwrap();
+        let mut switch_node =
+            ASTNode::new_switch_node(switch_info.case_values, switch_info.has_default);
+        switch_node.add_neighbor_node(condition_link, body_link);
+        Some(Box::new(switch_node))
+    }
+
+    // "case" const_expr ":" stmt
+    fn stmt_case(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let case_token = token_list.pop_head().unwrap();
+        if function_info.switch_infos.is_empty() {
+            error_exit("case is not in switch", case_token.token_pos);
+        }
+        let value_pos = match token_list.peek_head() {
+            Some(token) => token.token_pos,
+            None => invalid_token_exit("case requires value", token_list),
+        };
+        let value = match AST::conditional(token_list, function_info, program_info) {
+            Some(value_node) => value_node.eval_constant(),
+            None => None,
+        };
+        let value = match value {
+            Some(value) => value,
+            None => error_exit("case value must be constant expression", value_pos),
+        };
+        let switch_info = function_info.switch_infos.last_mut().unwrap();
+        let value = switch_info.condition_ty.convert_constant(value);
+        if switch_info.case_values.contains(&value) {
+            error_exit(&format!("duplicate case value {}", value), value_pos);
+        }
+        switch_info.case_values.push(value);
+        let case_index = switch_info.case_values.len() - 1;
+        if !token_list.consume_colon() {
+            invalid_token_exit("case requires ':'", token_list);
+        }
+        let mut case_node = ASTNode::new_case_node(case_index);
+        case_node.add_neighbor_node(AST::stmt(token_list, function_info, program_info), None);
+        Some(Box::new(case_node))
+    }
+
+    fn stmt_for(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
         if token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
             let mut for_node = ASTNode::new_for_node();
             let mut for_vec: Vec<Link> = vec![]; // for文用のvecを作成
-                                                 // 初期化式
-            if token_list.is_statement_end() {
+
+            // 初期化文, 宣言した変数はfor文の中だけで見える
+            function_info.enter_scope();
+            if token_list.is_type_name() {
+                for_vec.push(AST::declaration(token_list, function_info, program_info));
+            } else if token_list.is_statement_end() {
                 token_list.pop_head();
                 for_vec.push(None);
             } else {
-                for_vec.push(AST::expr(token_list));
+                let mut expr_stmt_node = ASTNode::new_expr_stmt_node();
+                expr_stmt_node
+                    .add_neighbor_node(AST::expr(token_list, function_info, program_info), None);
+                for_vec.push(Some(Box::new(expr_stmt_node)));
                 if !token_list.consume_statement_end() {
                     invalid_token_exit("for initialzer must be expression", token_list);
                 }
@@ -295,7 +1086,7 @@ impl AST {
                 token_list.pop_head();
                 for_vec.push(None);
             } else {
-                for_vec.push(AST::expr(token_list));
+                for_vec.push(AST::expr(token_list, function_info, program_info));
                 if !token_list.consume_statement_end() {
                     invalid_token_exit("for judge must be expression", token_list);
                 }
@@ -305,106 +1096,357 @@ impl AST {
                 token_list.pop_head();
                 for_vec.push(None);
             } else {
-                for_vec.push(AST::expr(token_list));
+                for_vec.push(AST::expr(token_list, function_info, program_info));
                 if !token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
                     invalid_token_exit("for updater must be expression", token_list);
                 }
             }
-            for_node.left = AST::stmt(token_list);
+            for_node.left = AST::stmt(token_list, function_info, program_info);
+            function_info.leave_scope();
             for_node.vec = Some(for_vec);
-            return Some(Box::new(for_node));
+            Some(Box::new(for_node))
         } else {
             if let Some(valid_token) = token_list.pop_head() {
                 error_exit("for condition must start '(' ", valid_token.token_pos);
             } else {
                 // テキスト終端に要求エラーを立てる
-                let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
+                let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
                 error_exit("for condition is not given", tail_pos);
             }
         }
     }
 
     // expr  = assign
-    fn expr(token_list: &mut TokenList) -> Link {
-        AST::assign(token_list)
+    fn expr(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        AST::assign(token_list, function_info, program_info)
     }
 
     // assign = equality ("=" assign)?
-    fn assign(token_list: &mut TokenList) -> Link {
-        let mut assign_link = AST::equality(token_list);
+    fn assign(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut assign_link = AST::conditional(token_list, function_info, program_info);
         // assignは左辺値が変数でないかのチェックをASTのコンパイル時に行うので,
         // tokenの位置を取得する必要がある
         if token_list.is_assign() {
             let assign_token = token_list.pop_head().unwrap();
             let mut assign_node = ASTNode::new_assign_node(assign_token.token_pos);
-            assign_node.add_neighbor_node(assign_link.take(), AST::assign(token_list));
+            assign_node.add_neighbor_node(
+                assign_link.take(),
+                AST::assign(token_list, function_info, program_info),
+            );
+            assign_node.set_type(assign_token.token_pos);
+            assign_link = Some(Box::new(assign_node));
+        } else if let Some(operation) = token_list.is_compound_assign() {
+            let assign_token = token_list.pop_head().unwrap();
+            let mut assign_node =
+                ASTNode::new_compound_assign_node(operation, assign_token.token_pos);
+            assign_node.add_neighbor_node(
+                assign_link.take(),
+                AST::assign(token_list, function_info, program_info),
+            );
+            assign_node.set_type(assign_token.token_pos);
             assign_link = Some(Box::new(assign_node));
         }
         assign_link
     }
 
-    // equality   = relational ("==" relational | "!=" relational)*
-    fn equality(token_list: &mut TokenList) -> Link {
-        let mut equality_link = AST::relational(token_list);
+    // conditional = logor ("?" expr ":" conditional)?
+    // 右結合なので, :の後は再びconditionalとして読む
+    fn conditional(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let condition_link = AST::logor(token_list, function_info, program_info);
+        if !token_list.is_question() {
+            return condition_link;
+        }
+        let question_token = token_list.pop_head().unwrap();
+        let mut conditional_node = ASTNode::new_conditional_node(question_token.token_pos);
+        let then_link = AST::expr(token_list, function_info, program_info);
+        if !token_list.consume_colon() {
+            invalid_token_exit("conditional expression requires ':'", token_list);
+        }
+        let else_link = AST::conditional(token_list, function_info, program_info);
+        conditional_node.add_neighbor_node(condition_link, then_link);
+        conditional_node.vec = Some(vec![else_link]);
+        conditional_node.set_type(question_token.token_pos);
+        Some(Box::new(conditional_node))
+    }
+
+    // logor = logand ("||" logand)*
+    fn logor(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut logor_link = AST::logand(token_list, function_info, program_info);
+        while token_list.is_operation(OperationKind::LogicalOr) {
+            let logor_token = token_list.pop_head().unwrap();
+            let mut logor_node = ASTNode::new_operand_node(OperationKind::LogicalOr);
+            logor_node.add_neighbor_node(
+                logor_link.take(),
+                AST::logand(token_list, function_info, program_info),
+            );
+            logor_node.set_type(logor_token.token_pos);
+            logor_link = Some(Box::new(logor_node));
+        }
+        logor_link
+    }
+
+    // logand = bitor ("&&" bitor)*
+    fn logand(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut logand_link = AST::bitor(token_list, function_info, program_info);
+        while token_list.is_operation(OperationKind::LogicalAnd) {
+            let logand_token = token_list.pop_head().unwrap();
+            let mut logand_node = ASTNode::new_operand_node(OperationKind::LogicalAnd);
+            logand_node.add_neighbor_node(
+                logand_link.take(),
+                AST::bitor(token_list, function_info, program_info),
+            );
+            logand_node.set_type(logand_token.token_pos);
+            logand_link = Some(Box::new(logand_node));
+        }
+        logand_link
+    }
+
+    // bitor = bitxor ("|" bitxor)*
+    fn bitor(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut bitor_link = AST::bitxor(token_list, function_info, program_info);
 
         loop {
-            if token_list.consume_operation(OperationKind::Eq) {
-                let mut equality_node = ASTNode::new_operand_node(OperationKind::Eq);
-                equality_node.add_neighbor_node(equality_link.take(), AST::relational(token_list));
-                equality_link = Some(Box::new(equality_node));
-            } else if token_list.consume_operation(OperationKind::Not) {
-                let mut equality_node = ASTNode::new_operand_node(OperationKind::Not);
-                equality_node.add_neighbor_node(equality_link.take(), AST::relational(token_list));
-                equality_link = Some(Box::new(equality_node));
+            if token_list.is_operation(OperationKind::BitOr) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut bitor_node = ASTNode::new_operand_node(OperationKind::BitOr);
+                bitor_node.add_neighbor_node(
+                    bitor_link.take(),
+                    AST::bitxor(token_list, function_info, program_info),
+                );
+                bitor_node.set_type(operation_token.token_pos);
+                bitor_link = Some(Box::new(bitor_node));
             } else {
                 break;
             }
         }
-        equality_link
+        bitor_link
     }
 
-    // relational = add ("<" add | "<=" add | ">" add | ">=" add)*
-    fn relational(token_list: &mut TokenList) -> Link {
-        let mut relational_link = AST::add(token_list);
+    // bitxor = bitand ("^" bitand)*
+    fn bitxor(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut bitxor_link = AST::bitand(token_list, function_info, program_info);
 
         loop {
-            // Gt,Geは左辺と右辺を逆転させてLt, Leで評価する
-            if token_list.consume_operation(OperationKind::Gt) {
-                let mut relational_node = ASTNode::new_operand_node(OperationKind::Lt);
-                relational_node.add_neighbor_node(AST::add(token_list), relational_link.take());
-                relational_link = Some(Box::new(relational_node));
-            } else if token_list.consume_operation(OperationKind::Ge) {
-                let mut relational_node = ASTNode::new_operand_node(OperationKind::Le);
-                relational_node.add_neighbor_node(AST::add(token_list), relational_link.take());
-                relational_link = Some(Box::new(relational_node));
-            } else if token_list.consume_operation(OperationKind::Lt) {
-                let mut relational_node = ASTNode::new_operand_node(OperationKind::Lt);
-                relational_node.add_neighbor_node(relational_link.take(), AST::add(token_list));
-                relational_link = Some(Box::new(relational_node));
-            } else if token_list.consume_operation(OperationKind::Le) {
-                let mut relational_node = ASTNode::new_operand_node(OperationKind::Le);
-                relational_node.add_neighbor_node(relational_link.take(), AST::add(token_list));
-                relational_link = Some(Box::new(relational_node));
+            if token_list.is_operation(OperationKind::BitXor) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut bitxor_node = ASTNode::new_operand_node(OperationKind::BitXor);
+                bitxor_node.add_neighbor_node(
+                    bitxor_link.take(),
+                    AST::bitand(token_list, function_info, program_info),
+                );
+                bitxor_node.set_type(operation_token.token_pos);
+                bitxor_link = Some(Box::new(bitxor_node));
             } else {
                 break;
             }
         }
-        relational_link
+        bitxor_link
     }
 
-    // add = mul ("+" mul | "-" mul)*
-    fn add(token_list: &mut TokenList) -> Link {
-        let mut add_link = AST::mul(token_list);
+    // bitand = equality ("&" equality)*
+    // 単項の&はReferenceトークンなので, 式の後に現れた場合は二項演算のandとして扱う
+    fn bitand(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut bitand_link = AST::equality(token_list, function_info, program_info);
 
         loop {
-            if token_list.consume_operation(OperationKind::Add) {
-                let mut add_node = ASTNode::new_operand_node(OperationKind::Add);
-                add_node.add_neighbor_node(add_link.take(), AST::mul(token_list));
-                add_link = Some(Box::new(add_node));
-            } else if token_list.consume_operation(OperationKind::Sub) {
-                let mut add_node = ASTNode::new_operand_node(OperationKind::Sub);
-                add_node.add_neighbor_node(add_link.take(), AST::mul(token_list));
-                add_link = Some(Box::new(add_node));
+            if token_list.is_reference() {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut bitand_node = ASTNode::new_operand_node(OperationKind::BitAnd);
+                bitand_node.add_neighbor_node(
+                    bitand_link.take(),
+                    AST::equality(token_list, function_info, program_info),
+                );
+                bitand_node.set_type(operation_token.token_pos);
+                bitand_link = Some(Box::new(bitand_node));
+            } else {
+                break;
+            }
+        }
+        bitand_link
+    }
+
+    // equality   = relational ("==" relational | "!=" relational)*
+    fn equality(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut equality_link = AST::relational(token_list, function_info, program_info);
+
+        loop {
+            if token_list.is_operation(OperationKind::Eq) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut equality_node = ASTNode::new_operand_node(OperationKind::Eq);
+                equality_node.add_neighbor_node(
+                    equality_link.take(),
+                    AST::relational(token_list, function_info, program_info),
+                );
+                equality_node.set_type(operation_token.token_pos);
+                equality_link = Some(Box::new(equality_node));
+            } else if token_list.is_operation(OperationKind::Not) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut equality_node = ASTNode::new_operand_node(OperationKind::Not);
+                equality_node.add_neighbor_node(
+                    equality_link.take(),
+                    AST::relational(token_list, function_info, program_info),
+                );
+                equality_node.set_type(operation_token.token_pos);
+                equality_link = Some(Box::new(equality_node));
+            } else {
+                break;
+            }
+        }
+        equality_link
+    }
+
+    // relational = add ("<" add | "<=" add | ">" add | ">=" add)*
+    fn relational(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut relational_link = AST::shift(token_list, function_info, program_info);
+
+        loop {
+            // Gt,Geは左辺と右辺を逆転させてLt, Leで評価する
+            if token_list.is_operation(OperationKind::Gt) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut relational_node = ASTNode::new_operand_node(OperationKind::Lt);
+                relational_node.add_neighbor_node(
+                    AST::shift(token_list, function_info, program_info),
+                    relational_link.take(),
+                );
+                relational_node.set_type(operation_token.token_pos);
+                relational_link = Some(Box::new(relational_node));
+            } else if token_list.is_operation(OperationKind::Ge) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut relational_node = ASTNode::new_operand_node(OperationKind::Le);
+                relational_node.add_neighbor_node(
+                    AST::shift(token_list, function_info, program_info),
+                    relational_link.take(),
+                );
+                relational_node.set_type(operation_token.token_pos);
+                relational_link = Some(Box::new(relational_node));
+            } else if token_list.is_operation(OperationKind::Lt) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut relational_node = ASTNode::new_operand_node(OperationKind::Lt);
+                relational_node.add_neighbor_node(
+                    relational_link.take(),
+                    AST::shift(token_list, function_info, program_info),
+                );
+                relational_node.set_type(operation_token.token_pos);
+                relational_link = Some(Box::new(relational_node));
+            } else if token_list.is_operation(OperationKind::Le) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut relational_node = ASTNode::new_operand_node(OperationKind::Le);
+                relational_node.add_neighbor_node(
+                    relational_link.take(),
+                    AST::shift(token_list, function_info, program_info),
+                );
+                relational_node.set_type(operation_token.token_pos);
+                relational_link = Some(Box::new(relational_node));
+            } else {
+                break;
+            }
+        }
+        relational_link
+    }
+
+    // shift = add ("<<" add | ">>" add)*
+    fn shift(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut shift_link = AST::add(token_list, function_info, program_info);
+
+        loop {
+            if token_list.is_operation(OperationKind::Shl) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut shift_node = ASTNode::new_operand_node(OperationKind::Shl);
+                shift_node.add_neighbor_node(
+                    shift_link.take(),
+                    AST::add(token_list, function_info, program_info),
+                );
+                shift_node.set_type(operation_token.token_pos);
+                shift_link = Some(Box::new(shift_node));
+            } else if token_list.is_operation(OperationKind::Shr) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut shift_node = ASTNode::new_operand_node(OperationKind::Shr);
+                shift_node.add_neighbor_node(
+                    shift_link.take(),
+                    AST::add(token_list, function_info, program_info),
+                );
+                shift_node.set_type(operation_token.token_pos);
+                shift_link = Some(Box::new(shift_node));
+            } else {
+                break;
+            }
+        }
+        shift_link
+    }
+
+    // add = mul ("+" mul | "-" mul)*
+    fn add(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut add_link = AST::mul(token_list, function_info, program_info);
+
+        loop {
+            if token_list.is_operation(OperationKind::Add) {
+                // ポインタ演算の型チェックでエラー位置を示せるようにする
+                let operation_token = token_list.pop_head().unwrap();
+                let mut add_node = ASTNode::new_operand_node(OperationKind::Add);
+                add_node.add_neighbor_node(
+                    add_link.take(),
+                    AST::mul(token_list, function_info, program_info),
+                );
+                add_node.set_type(operation_token.token_pos);
+                add_link = Some(Box::new(add_node));
+            } else if token_list.is_operation(OperationKind::Sub) {
+                // ポインタ演算の型チェックでエラー位置を示せるようにする
+                let operation_token = token_list.pop_head().unwrap();
+                let mut add_node = ASTNode::new_operand_node(OperationKind::Sub);
+                add_node.add_neighbor_node(
+                    add_link.take(),
+                    AST::mul(token_list, function_info, program_info),
+                );
+                add_node.set_type(operation_token.token_pos);
+                add_link = Some(Box::new(add_node));
             } else {
                 break;
             }
@@ -413,17 +1455,42 @@ impl AST {
     }
 
     // mul  = unary ("*" unary | "/" unary)*
-    fn mul(token_list: &mut TokenList) -> Link {
-        let mut mul_link = AST::urany(token_list);
+    fn mul(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut mul_link = AST::urany(token_list, function_info, program_info);
 
         loop {
-            if token_list.consume_operation(OperationKind::Mul) {
+            if token_list.is_operation(OperationKind::Mul) {
+                // ポインタ演算の型チェックでエラー位置を示せるようにする
+                let operation_token = token_list.pop_head().unwrap();
                 let mut mul_node = ASTNode::new_operand_node(OperationKind::Mul);
-                mul_node.add_neighbor_node(mul_link.take(), AST::urany(token_list));
+                mul_node.add_neighbor_node(
+                    mul_link.take(),
+                    AST::urany(token_list, function_info, program_info),
+                );
+                mul_node.set_type(operation_token.token_pos);
                 mul_link = Some(Box::new(mul_node));
-            } else if token_list.consume_operation(OperationKind::Div) {
+            } else if token_list.is_operation(OperationKind::Div) {
+                // ポインタ演算の型チェックでエラー位置を示せるようにする
+                let operation_token = token_list.pop_head().unwrap();
                 let mut mul_node = ASTNode::new_operand_node(OperationKind::Div);
-                mul_node.add_neighbor_node(mul_link.take(), AST::urany(token_list));
+                mul_node.add_neighbor_node(
+                    mul_link.take(),
+                    AST::urany(token_list, function_info, program_info),
+                );
+                mul_node.set_type(operation_token.token_pos);
+                mul_link = Some(Box::new(mul_node));
+            } else if token_list.is_operation(OperationKind::Mod) {
+                let operation_token = token_list.pop_head().unwrap();
+                let mut mul_node = ASTNode::new_operand_node(OperationKind::Mod);
+                mul_node.add_neighbor_node(
+                    mul_link.take(),
+                    AST::urany(token_list, function_info, program_info),
+                );
+                mul_node.set_type(operation_token.token_pos);
                 mul_link = Some(Box::new(mul_node));
             } else {
                 break;
@@ -432,37 +1499,241 @@ impl AST {
         mul_link
     }
 
-    // unary = ("+" | "-")? primary
-    fn urany(token_list: &mut TokenList) -> Link {
+    // unary = ("+" | "-") unary
+    //       | "*" unary
+    //       | "&" unary
+    //       | postfix
+    fn urany(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
         if token_list.consume_operation(OperationKind::Add) {
-            return AST::primary(token_list);
-        } else if token_list.consume_operation(OperationKind::Sub) {
+            return AST::urany(token_list, function_info, program_info);
+        } else if token_list.is_operation(OperationKind::Sub) {
+            let sub_token = token_list.pop_head().unwrap();
+            let operand_link = AST::urany(token_list, function_info, program_info);
+            // 0.0 - 0.0は+0.0になるので, 浮動小数点数は符号ビットを反転する
+            let operand_ty = operand_link.as_ref().and_then(|node| node.ty.clone());
+            if let Some(ty) = operand_ty.filter(|ty| ty.is_float()) {
+                let mut negation_node = ASTNode::new_negation_node(ty);
+                negation_node.add_neighbor_node(operand_link, None);
+                return Some(Box::new(negation_node));
+            }
             let mut unary_node = ASTNode::new_operand_node(OperationKind::Sub);
-            let zoro_node = ASTNode::new_primary_node(PrimaryNodeKind::Number(0));
-            unary_node.add_neighbor_node(Some(Box::new(zoro_node)), AST::primary(token_list));
+            let zoro_node = ASTNode::new_primary_node(PrimaryNodeKind::Number(0), Type::Int);
+            unary_node.add_neighbor_node(Some(Box::new(zoro_node)), operand_link);
+            unary_node.set_type(sub_token.token_pos);
             return Some(Box::new(unary_node));
         } else if token_list.is_operation(OperationKind::Mul) {
             // アドレスは対象が変数でないかのチェックをASTのコンパイル時に行うので,
             // tokenの位置を取得する必要がある
             let dereference_token = token_list.pop_head().unwrap();
             let mut dereference_node = ASTNode::new_deference_node(dereference_token.token_pos);
-            dereference_node.add_neighbor_node(AST::urany(token_list), None);
+            dereference_node
+                .add_neighbor_node(AST::urany(token_list, function_info, program_info), None);
+            dereference_node.set_type(dereference_token.token_pos);
             return Some(Box::new(dereference_node));
         } else if token_list.is_reference() {
             // アドレスは対象が変数でないかのチェックをASTのコンパイル時に行うので,
             // tokenの位置を取得する必要がある
             let reference_token = token_list.pop_head().unwrap();
+            let operand_link = AST::urany(token_list, function_info, program_info);
+            // 関数名の値は既に関数のアドレスなので, &fはfと同じ
+            if let Some(ASTNodeKind::Primary(PrimaryNodeKind::FunctionAddress(_))) =
+                operand_link.as_ref().map(|node| &node.node_kind)
+            {
+                return operand_link;
+            }
             let mut reference_node = ASTNode::new_reference_node(reference_token.token_pos);
-            reference_node.add_neighbor_node(AST::urany(token_list), None);
+            reference_node.add_neighbor_node(operand_link, None);
+            reference_node.set_type(reference_token.token_pos);
             return Some(Box::new(reference_node));
+        } else if token_list.is_operation(OperationKind::LogicalNot) {
+            let not_token = token_list.pop_head().unwrap();
+            let mut not_node = ASTNode::new_operand_node(OperationKind::LogicalNot);
+            not_node.add_neighbor_node(AST::urany(token_list, function_info, program_info), None);
+            not_node.set_type(not_token.token_pos);
+            return Some(Box::new(not_node));
+        } else if token_list.is_operation(OperationKind::BitNot) {
+            let not_token = token_list.pop_head().unwrap();
+            let mut not_node = ASTNode::new_operand_node(OperationKind::BitNot);
+            not_node.add_neighbor_node(AST::urany(token_list, function_info, program_info), None);
+            not_node.set_type(not_token.token_pos);
+            return Some(Box::new(not_node));
+        } else if token_list.is_increment() || token_list.is_decrement() {
+            // ++a は a += 1 として扱う
+            let operation = if token_list.is_increment() {
+                OperationKind::Add
+            } else {
+                OperationKind::Sub
+            };
+            let operation_token = token_list.pop_head().unwrap();
+            let mut assign_node =
+                ASTNode::new_compound_assign_node(operation, operation_token.token_pos);
+            let one_node = ASTNode::new_primary_node(PrimaryNodeKind::Number(1), Type::Int);
+            assign_node.add_neighbor_node(
+                AST::urany(token_list, function_info, program_info),
+                Some(Box::new(one_node)),
+            );
+            assign_node.set_type(operation_token.token_pos);
+            return Some(Box::new(assign_node));
+        } else if token_list.is_sizeof() {
+            // sizeofはコンパイル時に定数へ置き換える
+            let sizeof_token = token_list.pop_head().unwrap();
+            let ty = if token_list.is_parenthesized_type_name() {
+                token_list.comsume_parentheses(ParenthesesKind::LeftParentheses);
+                let ty = pop_type_name(token_list, Some(&mut *function_info), program_info);
+                if !token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
+                    invalid_token_exit("sizeof type name requires ')'", token_list);
+                }
+                ty
+            } else {
+                match AST::urany(token_list, function_info, program_info) {
+                    Some(node) => match node.ty {
+                        Some(ty) => ty,
+                        None => error_exit("sizeof operand has no type", sizeof_token.token_pos),
+                    },
+                    None => error_exit("sizeof requires operand", sizeof_token.token_pos),
+                }
+            };
+            let size_node =
+                ASTNode::new_primary_node(PrimaryNodeKind::Number(ty.size() as i64), Type::ULong);
+            return Some(Box::new(size_node));
+        } else if token_list.is_parenthesized_type_name() {
+            // (type)expr はキャスト
+            let cast_token = token_list.pop_head().unwrap();
+            let ty = pop_type_name(token_list, Some(&mut *function_info), program_info);
+            if !token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
+                invalid_token_exit("cast type name requires ')'", token_list);
+            }
+            let operand_link = AST::urany(token_list, function_info, program_info);
+            let operand_ty = operand_link.as_ref().and_then(|node| node.ty.clone());
+            if ty.is_struct() {
+                error_exit("cannot cast to struct", cast_token.token_pos);
+            }
+            if ty.is_array() {
+                error_exit("cannot cast to array", cast_token.token_pos);
+            }
+            match operand_ty {
+                Some(Type::Void) if ty != Type::Void => {
+                    error_exit("void value is not ignored", cast_token.token_pos)
+                }
+                Some(operand_ty) if operand_ty.is_struct() => {
+                    error_exit("cannot cast struct value", cast_token.token_pos)
+                }
+                Some(operand_ty) if operand_ty.is_integer() && ty.is_integer() => {
+                    return convert_link(operand_link, &ty);
+                }
+                Some(_) => {}
+                None => error_exit("cast requires operand", cast_token.token_pos),
+            }
+            let mut cast_node = ASTNode::new_cast_node(ty);
+            cast_node.add_neighbor_node(operand_link, None);
+            return Some(Box::new(cast_node));
         }
-        return AST::primary(token_list);
+        AST::postfix(token_list, function_info, program_info)
     }
 
-    // primary    = num | ident ("(" ")")? | "(" expr ")"
-    fn primary(token_list: &mut TokenList) -> Link {
+    // postfix = primary ("[" expr "]" | "." ident | "->" ident | "++" | "--" | "(" (assign ("," assign)*)? ")")*
+    // a[i] は *(a + i), p->x は (*p).x として扱う
+    fn postfix(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        let mut postfix_link = AST::primary(token_list, function_info, program_info);
+
+        loop {
+            if token_list.is_increment() || token_list.is_decrement() {
+                // a++ は 1を足して演算前の値を返す
+                let operation = if token_list.is_increment() {
+                    OperationKind::Add
+                } else {
+                    OperationKind::Sub
+                };
+                let operation_token = token_list.pop_head().unwrap();
+                let mut assign_node =
+                    ASTNode::new_postfix_assign_node(operation, operation_token.token_pos);
+                let one_node = ASTNode::new_primary_node(PrimaryNodeKind::Number(1), Type::Int);
+                assign_node.add_neighbor_node(postfix_link.take(), Some(Box::new(one_node)));
+                assign_node.set_type(operation_token.token_pos);
+                postfix_link = Some(Box::new(assign_node));
+                continue;
+            } else if token_list.is_dot() {
+                let dot_token = token_list.pop_head().unwrap();
+                postfix_link = AST::member(postfix_link, token_list, dot_token.token_pos);
+                continue;
+            } else if token_list.is_arrow() {
+                let arrow_token = token_list.pop_head().unwrap();
+                let mut dereference_node = ASTNode::new_deference_node(arrow_token.token_pos);
+                dereference_node.add_neighbor_node(postfix_link.take(), None);
+                dereference_node.set_type(arrow_token.token_pos);
+                postfix_link = AST::member(
+                    Some(Box::new(dereference_node)),
+                    token_list,
+                    arrow_token.token_pos,
+                );
+                continue;
+            } else if token_list.is_parentheses(ParenthesesKind::LeftParentheses) {
+                // table[i](x), (*fp)(x) のように式の値のアドレスを呼び出す
+                let call_token = token_list.pop_head().unwrap();
+                let args_vec = AST::pop_call_args(token_list, function_info, program_info);
+                postfix_link =
+                    AST::function_pointer_call(postfix_link, args_vec, call_token.token_pos);
+                continue;
+            } else if !token_list.is_brackets(BracketsKind::LeftBrackets) {
+                break;
+            }
+            let bracket_token = token_list.pop_head().unwrap();
+            let mut add_node = ASTNode::new_operand_node(OperationKind::Add);
+            add_node.add_neighbor_node(
+                postfix_link.take(),
+                AST::expr(token_list, function_info, program_info),
+            );
+            add_node.set_type(bracket_token.token_pos);
+            if !token_list.consume_brackets(BracketsKind::RightBrackets) {
+                invalid_token_exit("subscript is not closed", token_list);
+            }
+            let mut dereference_node = ASTNode::new_deference_node(bracket_token.token_pos);
+            dereference_node.add_neighbor_node(Some(Box::new(add_node)), None);
+            dereference_node.set_type(bracket_token.token_pos);
+            postfix_link = Some(Box::new(dereference_node));
+        }
+        postfix_link
+    }
+
+    // 構造体のメンバを参照するノードを作る
+    // メンバが見つからない場合はメンバ名の位置にエラーを立てる
+    fn member(struct_link: Link, token_list: &mut TokenList, access_pos: usize) -> Link {
+        let struct_ref = match struct_link.as_ref().and_then(|node| node.ty.clone()) {
+            Some(Type::Struct(struct_ref)) => struct_ref,
+            _ => error_exit("member access requires struct", access_pos),
+        };
+        // s.cb(x) のメンバ名は関数呼び出しトークンになるので, 名前だけを取り出して
+        // 残った"("はpostfixで関数ポインタの呼び出しとして扱う
+        let member_pos = token_list.peek_head().as_ref().map(|token| token.token_pos);
+        let (member_name, member_pos) = match token_list.consume_functioncall() {
+            Some(member_name) => (member_name, member_pos.unwrap()),
+            None => token_list.expect_identifier(),
+        };
+        let member = match struct_ref.find_member(&member_name) {
+            Some(member) => member,
+            None => error_exit(&format!("no member named {}", member_name), member_pos),
+        };
+        let mut member_node = ASTNode::new_member_node(member.offset, member.ty);
+        member_node.add_neighbor_node(struct_link, None);
+        Some(Box::new(member_node))
+    }
+
+    // primary    = num | float_num | str | ident | ident "(" (assign ("," assign)*)? ")" | "(" expr ")"
+    fn primary(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
         if token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
-            let node = AST::expr(token_list);
+            let node = AST::expr(token_list, function_info, program_info);
             if token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
                 return node;
             } else {
@@ -472,63 +1743,807 @@ impl AST {
 
         // 関数呼び出しの場合
         // 識別子の次が(の場合には関数呼び出しトークンとしている
+        let call_pos = token_list.peek_head().as_ref().map(|token| token.token_pos);
         if let Some(function_name) = token_list.consume_functioncall() {
             // "(" token取り出し
             token_list.pop_head();
-            let mut function_call_node = ASTNode::new_funtioncall_node(function_name);
-            let mut args_vec: Vec<Link> = vec![];
-            while !token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
-                if !token_list.is_empty() {
-                    if args_vec.len() == 6 {
-                        if let Some(valid_token) = token_list.pop_head() {
-                            error_exit("canoot take to much argument", valid_token.token_pos);
-                        } else {
-                            // テキスト終端に要求エラーを立てる
-                            let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
-                            error_exit("function call is not closed", tail_pos);
-                        }
+            let args_vec = AST::pop_call_args(token_list, function_info, program_info);
+            let call_pos = call_pos.unwrap();
+            // 関数ポインタの変数は関数名より優先して, 変数の値のアドレスを呼び出す
+            if let Some(variable_link) =
+                AST::find_variable(&function_name, function_info, program_info)
+            {
+                return AST::function_pointer_call(Some(variable_link), args_vec, call_pos);
+            }
+            let mut function_call_node = ASTNode::new_funtioncall_node(function_name.clone());
+            if args_vec
+                .iter()
+                .any(|arg| arg.as_ref().unwrap().ty == Some(Type::Void))
+            {
+                error_exit("void value is not ignored", call_pos);
+            }
+            let return_ty =
+                program_info.check_function_call(&function_name, args_vec.len(), call_pos);
+            // 宣言済みの関数の引数は仮引数の型に変換する
+            // 宣言のない関数にはfloatをdoubleにして渡す
+            let arg_types = program_info
+                .find_function(&function_name)
+                .map(|function| function.arg_types.clone());
+            let args_vec = args_vec
+                .into_iter()
+                .enumerate()
+                .map(|(arg_index, arg)| match &arg_types {
+                    Some(arg_types) => convert_link(arg, &arg_types[arg_index]),
+                    None if arg.as_ref().unwrap().ty == Some(Type::Float) => {
+                        convert_link(arg, &Type::Double)
                     }
+                    None => arg,
+                })
+                .collect();
+            function_call_node.vec = Some(args_vec);
+            function_call_node.ty = Some(return_ty);
+            return Some(Box::new(function_call_node));
+        }
+
+        AST::primary_value(token_list, function_info, program_info)
+    }
 
-                    let primarykind = token_list.expect_primary();
-                    let primary_node = ASTNode::new_primary_node(primarykind);
-                    args_vec.push(Some(Box::new(primary_node)));
-
-                    if !token_list.consume_commma() {
-                        if token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
-                            break;
-                        }
-
-                        if let Some(valid_token) = token_list.pop_head() {
-                            error_exit(
-                                "function args must be separated by comma",
-                                valid_token.token_pos,
-                            );
-                        } else {
-                            // テキスト終端に要求エラーを立てる
-                            let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
-                            error_exit("function call is not closed", tail_pos);
-                        }
+    // 関数呼び出しの引数を")"まで取り出す, "("は取り出し済み
+    fn pop_call_args(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Vec<Link> {
+        let mut args_vec: Vec<Link> = vec![];
+        while !token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
+            if !token_list.is_empty() {
+                // 引数の区切りの","と区別するため, 引数はassignとして解析する
+                args_vec.push(AST::assign(token_list, function_info, program_info));
+
+                if !token_list.consume_commma() {
+                    if token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
+                        break;
+                    }
+
+                    if let Some(valid_token) = token_list.pop_head() {
+                        error_exit(
+                            "function args must be separated by comma",
+                            valid_token.token_pos,
+                        );
+                    } else {
+                        // テキスト終端に要求エラーを立てる
+                        let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
+                        error_exit("function call is not closed", tail_pos);
                     }
-                } else {
-                    // テキスト終端に要求エラーを立てる
-                    let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
-                    error_exit("function call is not closed", tail_pos);
                 }
+            } else {
+                // テキスト終端に要求エラーを立てる
+                let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
+                error_exit("function call is not closed", tail_pos);
             }
-            function_call_node.vec = Some(args_vec);
-            return Some(Box::new(function_call_node));
+        }
+        args_vec
+    }
+
+    // 関数ポインタ(または*fpのような関数型の式)の値のアドレスを呼び出す
+    // 引数は指す先の関数の仮引数の型に変換する
+    fn function_pointer_call(callee_link: Link, args_vec: Vec<Link>, call_pos: usize) -> Link {
+        let callee_ty = callee_link.as_ref().unwrap().ty.clone().unwrap();
+        let (return_ty, arg_types) = match callee_ty.callee_function() {
+            Some((return_ty, arg_types)) => (return_ty.clone(), arg_types.clone()),
+            None => error_exit("called object is not a function", call_pos),
+        };
+        if arg_types.len() != args_vec.len() {
+            error_exit(
+                &format!(
+                    "function pointer takes {} arguments but {} were given",
+                    arg_types.len(),
+                    args_vec.len()
+                ),
+                call_pos,
+            );
+        }
+        if args_vec
+            .iter()
+            .any(|arg| arg.as_ref().unwrap().ty == Some(Type::Void))
+        {
+            error_exit("void value is not ignored", call_pos);
+        }
+        let args_vec = args_vec
+            .into_iter()
+            .zip(arg_types.iter())
+            .map(|(arg, arg_ty)| convert_link(arg, arg_ty))
+            .collect();
+        let mut call_node = ASTNode::new_function_pointer_call_node();
+        call_node.add_neighbor_node(callee_link, None);
+        call_node.vec = Some(args_vec);
+        call_node.ty = Some(return_ty);
+        Some(Box::new(call_node))
+    }
+
+    // 変数名を解決する, ローカル変数がグローバル変数より優先される
+    fn find_variable(
+        identifier: &str,
+        function_info: &FuntionInfo,
+        program_info: &ProgramInfo,
+    ) -> Link {
+        if let Some(variable) = function_info.find_local_variable(identifier) {
+            let primary_node = ASTNode::new_primary_node(
+                PrimaryNodeKind::LocalVariable(variable.offset),
+                variable.ty.clone(),
+            );
+            return Some(Box::new(primary_node));
+        }
+        if let Some(variable) = program_info.find_global_variable(identifier) {
+            let primary_node = ASTNode::new_primary_node(
+                PrimaryNodeKind::GlobalVariable(identifier.to_string()),
+                variable.ty.clone(),
+            );
+            return Some(Box::new(primary_node));
+        }
+        None
+    }
+
+    // 数値, 文字列リテラルか変数
+    fn primary_value(
+        token_list: &mut TokenList,
+        function_info: &mut FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> Link {
+        if let Some((identifier, identifier_pos)) = token_list.consume_identifier() {
+            if let Some(variable_link) =
+                AST::find_variable(&identifier, function_info, program_info)
+            {
+                return Some(variable_link);
+            } else if let Some(function) = program_info.find_function(&identifier) {
+                // 宣言済みの関数名は関数ポインタとして扱う
+                let ty = Type::pointer_to(Type::function(
+                    function.return_ty.clone(),
+                    function.arg_types.clone(),
+                ));
+                let primary_node =
+                    ASTNode::new_primary_node(PrimaryNodeKind::FunctionAddress(identifier), ty);
+                return Some(Box::new(primary_node));
+            } else {
+                error_exit(
+                    &format!("undefined variable {}", identifier),
+                    identifier_pos,
+                );
+            }
+        }
+
+        if let Some((num, ty)) = token_list.consume_number_literal() {
+            let primary_node = ASTNode::new_primary_node(PrimaryNodeKind::Number(num), ty);
+            return Some(Box::new(primary_node));
         }
 
-        let primarykind = token_list.expect_primary();
-        let primary_node = ASTNode::new_primary_node(primarykind);
-        return Some(Box::new(primary_node));
+        if let Some((value, ty)) = token_list.consume_float_literal() {
+            let primary_node =
+                ASTNode::new_primary_node(PrimaryNodeKind::FloatNumber(value.to_bits()), ty);
+            return Some(Box::new(primary_node));
+        }
+
+        // 文字列リテラルはNUL終端を含めたcharの配列
+        if let Some(bytes) = token_list.consume_string_literal() {
+            let ty = Type::array_of(Type::Char, bytes.len() + 1);
+            let primary_node = ASTNode::new_primary_node(PrimaryNodeKind::StringLiteral(bytes), ty);
+            return Some(Box::new(primary_node));
+        }
+        invalid_token_exit("expect number, string or variable token", token_list);
     }
 }
 
+#[derive(Clone)]
+pub struct LocalVariable {
+    pub name: String,
+    pub ty: Type,
+    pub offset: usize, // (offset from bsp)
+}
+
 pub struct FuntionInfo {
     pub function_name: String,
-    pub args_count: usize,
+    pub return_ty: Type,
+    pub args: Vec<LocalVariable>,
+    // 全てのスコープを通して必要になる最大のスタックサイズ
     pub local_stack_size: usize,
+    // 解析中の位置で使っているスタックサイズ, スコープを抜けると戻す
+    current_stack_size: usize,
+    // ブロックのスコープ, 内側のものが末尾
+    // 先頭は引数のスコープ, 2番目が関数本体のスコープになる
+    scopes: Vec<Scope>,
+    // 解析中のswitch文, 内側のものが末尾
+    switch_infos: Vec<SwitchInfo>,
+    // 関数内で定義したラベルとgotoで参照したラベル (名前, 文字列中の位置)
+    labels: Vec<(String, usize)>,
+    goto_labels: Vec<(String, usize)>,
+}
+
+// ブロック内で宣言したローカル変数と構造体のタグ
+struct Scope {
+    local_variables: Vec<LocalVariable>,
+    struct_tags: Vec<(String, StructRef)>,
+    // スコープに入った時点のスタックサイズ
+    // 抜ける際にここまで戻すことで, 重ならないスコープ同士でスタックを再利用する
+    stack_size: usize,
+}
+
+// switch文の中に現れたcaseとdefault
+struct SwitchInfo {
+    case_values: Vec<i64>, // 条件式の型に変換した値
+    has_default: bool,
+    condition_ty: Type,
+}
+
+impl FuntionInfo {
+    // 内側のスコープから順に探すので, 内側の宣言が外側を隠す
+    fn find_local_variable(&self, name: &str) -> Option<&LocalVariable> {
+        self.scopes.iter().rev().find_map(|scope| {
+            scope
+                .local_variables
+                .iter()
+                .find(|variable| variable.name == name)
+        })
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(Scope {
+            local_variables: vec![],
+            struct_tags: vec![],
+            stack_size: self.current_stack_size,
+        });
+    }
+
+    fn leave_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        self.current_stack_size = scope.stack_size;
+    }
+
+    fn add_label(&mut self, name: String, name_pos: usize) {
+        if self
+            .labels
+            .iter()
+            .any(|(label_name, _)| *label_name == name)
+        {
+            error_exit(&format!("label {} is already defined", name), name_pos);
+        }
+        self.labels.push((name, name_pos));
+    }
+
+    // gotoの飛び先が全て関数内で定義されているか確認する
+    fn check_goto_labels(&self) {
+        for (goto_label, goto_pos) in &self.goto_labels {
+            if !self
+                .labels
+                .iter()
+                .any(|(label_name, _)| label_name == goto_label)
+            {
+                error_exit(&format!("undefined label {}", goto_label), *goto_pos);
+            }
+        }
+    }
+
+    // ローカル変数を登録してスタック上の位置を割り当てる
+    fn add_local_variable(&mut self, name: String, ty: Type, name_pos: usize) -> LocalVariable {
+        // 引数と関数本体の一番外側の変数は同じスコープとして扱う
+        let checked_scopes = if self.scopes.len() == 2 { 2 } else { 1 };
+        let is_defined = self.scopes.iter().rev().take(checked_scopes).any(|scope| {
+            scope
+                .local_variables
+                .iter()
+                .any(|variable| variable.name == name)
+        });
+        if is_defined {
+            error_exit(&format!("variable {} is already defined", name), name_pos);
+        }
+        if ty.is_incomplete() {
+            error_exit(&format!("variable {} has incomplete type", name), name_pos);
+        }
+        self.current_stack_size = align_to(self.current_stack_size + ty.size(), ty.align());
+        self.local_stack_size = self.local_stack_size.max(self.current_stack_size);
+        let variable = LocalVariable {
+            name,
+            ty,
+            offset: self.current_stack_size,
+        };
+        let scope = self.scopes.last_mut().unwrap();
+        scope.local_variables.push(variable.clone());
+        variable
+    }
+}
+
+pub struct GlobalVariable {
+    pub name: String,
+    pub ty: Type,
+    pub initial_value: Option<i64>, // Noneの場合は0で初期化される
+}
+
+// プロトタイプ宣言または定義された関数
+pub struct FunctionDeclaration {
+    pub name: String,
+    pub return_ty: Type,
+    pub arg_types: Vec<Type>,
+    pub is_defined: bool,
+}
+
+// 関数をまたいで参照する情報
+pub struct ProgramInfo {
+    pub global_variables: Vec<GlobalVariable>,
+    // ファイルスコープで宣言した構造体のタグ
+    struct_tags: Vec<(String, StructRef)>,
+    // 翻訳単位内で宣言, 定義された関数
+    pub functions: Vec<FunctionDeclaration>,
+    // 呼び出し時点で未宣言だった関数呼び出し(関数名, 引数の数, 位置, 仮定した戻り値の型)
+    unresolved_calls: Vec<(String, usize, usize, Type)>,
+}
+
+impl ProgramInfo {
+    fn find_global_variable(&self, name: &str) -> Option<&GlobalVariable> {
+        self.global_variables
+            .iter()
+            .find(|variable| variable.name == name)
+    }
+
+    fn add_global_variable(&mut self, variable: GlobalVariable, name_pos: usize) {
+        if self.find_global_variable(&variable.name).is_some() {
+            error_exit(
+                &format!("variable {} is already defined", variable.name),
+                name_pos,
+            );
+        }
+        // 関数と変数は同じラベル名になるので, 同じ名前は使えない
+        if self.find_function(&variable.name).is_some() {
+            error_exit(
+                &format!(
+                    "{} is redeclared as different kind of symbol",
+                    variable.name
+                ),
+                name_pos,
+            );
+        }
+        if variable.ty.is_incomplete() {
+            error_exit(
+                &format!("variable {} has incomplete type", variable.name),
+                name_pos,
+            );
+        }
+        self.global_variables.push(variable);
+    }
+
+    fn find_function(&self, name: &str) -> Option<&FunctionDeclaration> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    // 関数のプロトタイプ宣言または定義を登録する
+    fn declare_function(
+        &mut self,
+        function_info: &FuntionInfo,
+        is_definition: bool,
+        name_pos: usize,
+    ) {
+        let name = &function_info.function_name;
+        if self.find_global_variable(name).is_some() {
+            error_exit(
+                &format!("{} is redeclared as different kind of symbol", name),
+                name_pos,
+            );
+        }
+        let arg_types: Vec<Type> = function_info
+            .args
+            .iter()
+            .map(|arg| arg.ty.clone())
+            .collect();
+        match self
+            .functions
+            .iter_mut()
+            .find(|function| &function.name == name)
+        {
+            Some(function) => {
+                if function.return_ty != function_info.return_ty || function.arg_types != arg_types
+                {
+                    error_exit(
+                        &format!("conflicting types for function {}", name),
+                        name_pos,
+                    );
+                }
+                if function.is_defined && is_definition {
+                    error_exit(&format!("function {} is already defined", name), name_pos);
+                }
+                function.is_defined |= is_definition;
+            }
+            None => {
+                self.functions.push(FunctionDeclaration {
+                    name: name.clone(),
+                    return_ty: function_info.return_ty.clone(),
+                    arg_types,
+                    is_defined: is_definition,
+                });
+            }
+        }
+    }
+
+    // 宣言済みの関数であれば引数の数を確認し, 未宣言であれば翻訳単位の末尾で確認する
+    // 呼び出し結果の型を返す
+    // 未宣言の関数はintを返すものとして扱い, 戻り値のeaxを符号拡張する
+    // mallocのようにポインタを返す関数はプロトタイプ宣言が必要
+    fn check_function_call(&mut self, name: &str, args_count: usize, call_pos: usize) -> Type {
+        match self.find_function(name) {
+            Some(function) => {
+                check_args_count(function, args_count, call_pos);
+                function.return_ty.clone()
+            }
+            None => {
+                let return_ty = Type::Int;
+                self.unresolved_calls.push((
+                    name.to_string(),
+                    args_count,
+                    call_pos,
+                    return_ty.clone(),
+                ));
+                return_ty
+            }
+        }
+    }
+
+    // 後から宣言された関数の呼び出しを確認する
+    // 呼び出し時に仮定した戻り値の型と宣言が異なる場合は, 呼び出し側の値が壊れるのでエラーにする
+    // 翻訳単位内で宣言されなかった関数は外部の関数として扱い, 確認しない
+    fn check_unresolved_calls(&self) {
+        for (name, args_count, call_pos, return_ty) in &self.unresolved_calls {
+            // 後から同じ名前のグローバル変数を定義した場合は, 変数のアドレスを呼び出すことになる
+            if self.find_global_variable(name).is_some() {
+                error_exit("called object is not a function", *call_pos);
+            }
+            if let Some(function) = self.find_function(name) {
+                if function.return_ty != *return_ty {
+                    error_exit(
+                        &format!("conflicting types for function {}", name),
+                        *call_pos,
+                    );
+                }
+                check_args_count(function, *args_count, *call_pos);
+            }
+        }
+    }
+}
+
+fn check_args_count(function: &FunctionDeclaration, args_count: usize, call_pos: usize) {
+    if function.arg_types.len() != args_count {
+        error_exit(
+            &format!(
+                "function {} takes {} arguments but {} were given",
+                function.name,
+                function.arg_types.len(),
+                args_count
+            ),
+            call_pos,
+        );
+    }
+}
+
+// 構造体のタグを探す, 関数内のタグを優先する
+fn find_struct_tag(
+    name: &str,
+    function_info: Option<&FuntionInfo>,
+    program_info: &ProgramInfo,
+) -> Option<StructRef> {
+    let local_tag = function_info.and_then(|function_info| {
+        function_info.scopes.iter().rev().find_map(|scope| {
+            scope
+                .struct_tags
+                .iter()
+                .find(|(tag_name, _)| tag_name == name)
+        })
+    });
+    local_tag
+        .or_else(|| {
+            program_info
+                .struct_tags
+                .iter()
+                .find(|(tag_name, _)| tag_name == name)
+        })
+        .map(|(_, struct_ref)| struct_ref.clone())
+}
+
+// 関数内であれば最も内側のブロックの, そうでなければファイルスコープのタグ一覧を返す
+fn current_struct_tags<'a>(
+    function_info: Option<&'a mut FuntionInfo>,
+    program_info: &'a mut ProgramInfo,
+) -> &'a mut Vec<(String, StructRef)> {
+    match function_info {
+        Some(function_info) => &mut function_info.scopes.last_mut().unwrap().struct_tags,
+        None => &mut program_info.struct_tags,
+    }
+}
+
+// 構造体の型を取り出す
+// "struct" ident? ("{" (type_name declarator ";")* "}")?
+fn pop_struct_type(
+    token_list: &mut TokenList,
+    mut function_info: Option<&mut FuntionInfo>,
+    program_info: &mut ProgramInfo,
+) -> Type {
+    let tag = token_list.consume_identifier();
+    if !token_list.comsume_braces(BracesKind::LeftBraces) {
+        // タグの参照, 宣言されていなければ不完全型として宣言する
+        let (tag_name, _) = match tag {
+            Some(tag) => tag,
+            None => invalid_token_exit("struct requires tag or member list", token_list),
+        };
+        if let Some(struct_ref) = find_struct_tag(&tag_name, function_info.as_deref(), program_info)
+        {
+            return Type::Struct(struct_ref);
+        }
+        let struct_ref = StructRef::new_incomplete();
+        current_struct_tags(function_info, program_info).push((tag_name, struct_ref.clone()));
+        return Type::Struct(struct_ref);
+    }
+
+    // 同じスコープに不完全型として宣言済みであれば, その型のメンバを決める
+    let struct_ref = match tag {
+        Some((tag_name, tag_pos)) => {
+            let struct_tags = current_struct_tags(function_info.as_deref_mut(), program_info);
+            match struct_tags.iter().find(|(name, _)| *name == tag_name) {
+                Some((_, struct_ref)) if struct_ref.is_complete() => {
+                    error_exit(&format!("struct {} is already defined", tag_name), tag_pos);
+                }
+                Some((_, struct_ref)) => struct_ref.clone(),
+                None => {
+                    let struct_ref = StructRef::new_incomplete();
+                    struct_tags.push((tag_name, struct_ref.clone()));
+                    struct_ref
+                }
+            }
+        }
+        None => StructRef::new_incomplete(),
+    };
+
+    let mut members: Vec<(String, Type)> = vec![];
+    while !token_list.comsume_braces(BracesKind::RightBraces) {
+        let (ty, name, name_pos) =
+            pop_variable_definition(token_list, function_info.as_deref_mut(), program_info);
+        if ty.is_incomplete() {
+            error_exit(&format!("member {} has incomplete type", name), name_pos);
+        }
+        if members.iter().any(|(member_name, _)| *member_name == name) {
+            error_exit(&format!("member {} is already defined", name), name_pos);
+        }
+        members.push((name, ty));
+        token_list.consume_statement_end();
+    }
+    struct_ref.set_members(members);
+    Type::Struct(struct_ref)
+}
+
+// 型名を取り出す
+// 構造体のタグを解決するため, 関数内ではfunction_infoを渡す
+// ("void" | integer_type | "float" | "double" | struct_decl) "*"* ("(" "*"+ ")" function_params)?
+//     ("[" num "]")*
+fn pop_type_name(
+    token_list: &mut TokenList,
+    mut function_info: Option<&mut FuntionInfo>,
+    program_info: &mut ProgramInfo,
+) -> Type {
+    let mut ty;
+    if token_list.consume_void() {
+        ty = Type::Void;
+    } else if let Some(integer_ty) = pop_integer_type(token_list) {
+        ty = integer_ty;
+    } else if token_list.consume_float() {
+        ty = Type::Float;
+    } else if token_list.consume_double() {
+        ty = Type::Double;
+    } else if token_list.consume_struct() {
+        ty = pop_struct_type(token_list, function_info.as_deref_mut(), program_info);
+    } else {
+        invalid_token_exit("expect type name", token_list);
+    }
+    while token_list.consume_operation(OperationKind::Mul) {
+        ty = Type::pointer_to(ty);
+    }
+    // キャストやsizeof, 仮引数で使う名前のない関数ポインタ型
+    if token_list.is_abstract_function_pointer() {
+        token_list.comsume_parentheses(ParenthesesKind::LeftParentheses);
+        let mut pointer_count = 0;
+        while token_list.consume_operation(OperationKind::Mul) {
+            pointer_count += 1;
+        }
+        token_list.comsume_parentheses(ParenthesesKind::RightParentheses);
+        ty = pop_function_type(token_list, ty, function_info, program_info);
+        for _ in 0..pointer_count {
+            ty = Type::pointer_to(ty);
+        }
+    }
+    // sizeof(char[10]) のような名前のない配列型
+    if token_list.is_brackets(BracketsKind::LeftBrackets) {
+        if ty.is_incomplete() {
+            invalid_token_exit("array has incomplete element type", token_list);
+        }
+        let array_lens = pop_array_lens(token_list);
+        ty = apply_array_lens(ty, array_lens);
+    }
+    ty
+}
+
+// unsigned long intのように並んだ整数の型指定子を取り出す
+// 整数の型指定子で始まらない場合はNone
+fn pop_integer_type(token_list: &mut TokenList) -> Option<Type> {
+    let type_pos = token_list.peek_head().as_ref()?.token_pos;
+    let (mut char_count, mut short_count, mut int_count, mut long_count) = (0, 0, 0, 0);
+    let (mut unsigned_count, mut signed_count) = (0, 0);
+    loop {
+        if token_list.consume_char() {
+            char_count += 1;
+        } else if token_list.consume_short() {
+            short_count += 1;
+        } else if token_list.consume_int() {
+            int_count += 1;
+        } else if token_list.consume_long() {
+            long_count += 1;
+        } else if token_list.consume_unsigned() {
+            unsigned_count += 1;
+        } else if token_list.consume_signed() {
+            signed_count += 1;
+        } else {
+            break;
+        }
+    }
+    if char_count + short_count + int_count + long_count + unsigned_count + signed_count == 0 {
+        return None;
+    }
+
+    // long longはlongと同じ8バイトとして扱う
+    let is_valid = char_count <= 1
+        && short_count <= 1
+        && int_count <= 1
+        && long_count <= 2
+        && unsigned_count + signed_count <= 1
+        && (char_count == 0 || short_count + int_count + long_count == 0)
+        && (short_count == 0 || long_count == 0);
+    if !is_valid {
+        error_exit("invalid combination of type specifiers", type_pos);
+    }
+    let is_unsigned = unsigned_count == 1;
+    let ty = if char_count == 1 {
+        if is_unsigned {
+            Type::UChar
+        } else {
+            Type::Char
+        }
+    } else if short_count == 1 {
+        if is_unsigned {
+            Type::UShort
+        } else {
+            Type::Short
+        }
+    } else if long_count > 0 {
+        if is_unsigned {
+            Type::ULong
+        } else {
+            Type::Long
+        }
+    } else if is_unsigned {
+        Type::UInt
+    } else {
+        Type::Int
+    };
+    Some(ty)
+}
+
+// 変数宣言の型と変数名を取り出す
+// type_name declarator
+fn pop_variable_definition(
+    token_list: &mut TokenList,
+    mut function_info: Option<&mut FuntionInfo>,
+    program_info: &mut ProgramInfo,
+) -> (Type, String, usize) {
+    let ty = pop_type_name(token_list, function_info.as_deref_mut(), program_info);
+    pop_variable_name(token_list, ty, function_info, program_info)
+}
+
+// 型名に続く変数名と配列の次元を取り出す
+// 関数ポインタの仮引数の型を解決するため, 関数内ではfunction_infoを渡す
+// declarator = ident ("[" num "]")*
+//            | "(" "*"+ ident ("[" num "]")* ")" function_params
+fn pop_variable_name(
+    token_list: &mut TokenList,
+    mut ty: Type,
+    function_info: Option<&mut FuntionInfo>,
+    program_info: &mut ProgramInfo,
+) -> (Type, String, usize) {
+    if !token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
+        let (identifier, identifier_pos) = token_list.expect_identifier();
+        let array_lens = pop_array_lens(token_list);
+        return (apply_array_lens(ty, array_lens), identifier, identifier_pos);
+    }
+
+    // int (*table[2])(int) は「intを返す関数へのポインタ」の2要素配列
+    let mut pointer_count = 0;
+    while token_list.consume_operation(OperationKind::Mul) {
+        pointer_count += 1;
+    }
+    if pointer_count == 0 {
+        invalid_token_exit("function pointer declarator requires '*'", token_list);
+    }
+    let (identifier, identifier_pos) = token_list.expect_identifier();
+    let array_lens = pop_array_lens(token_list);
+    if !token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
+        invalid_token_exit("declarator is not closed", token_list);
+    }
+    ty = pop_function_type(token_list, ty, function_info, program_info);
+    for _ in 0..pointer_count {
+        ty = Type::pointer_to(ty);
+    }
+    (apply_array_lens(ty, array_lens), identifier, identifier_pos)
+}
+
+// 変数名に続く配列の要素数を宣言順に取り出す
+// ("[" num "]")*
+fn pop_array_lens(token_list: &mut TokenList) -> Vec<usize> {
+    let mut array_lens = vec![];
+    while token_list.consume_brackets(BracketsKind::LeftBrackets) {
+        match token_list.consume_number() {
+            Some(len) if len > 0 => array_lens.push(len as usize),
+            _ => invalid_token_exit("array size must be positive number", token_list),
+        }
+        if !token_list.consume_brackets(BracketsKind::RightBrackets) {
+            invalid_token_exit("array size is not closed", token_list);
+        }
+    }
+    array_lens
+}
+
+// int a[2][3] は「intの3要素配列」の2要素配列なので, 後ろの次元から型を組み立てる
+fn apply_array_lens(mut ty: Type, array_lens: Vec<usize>) -> Type {
+    for len in array_lens.into_iter().rev() {
+        ty = Type::array_of(ty, len);
+    }
+    ty
+}
+
+// 関数ポインタが指す関数の型を取り出す, 仮引数名は型に影響しないので読み捨てる
+// function_params = "(" ("void" | type_name declarator? ("," type_name declarator?)*)? ")"
+fn pop_function_type(
+    token_list: &mut TokenList,
+    return_ty: Type,
+    mut function_info: Option<&mut FuntionInfo>,
+    program_info: &mut ProgramInfo,
+) -> Type {
+    if !return_ty.is_arithmetic() && !return_ty.is_pointer() && return_ty != Type::Void {
+        invalid_token_exit(
+            "function must return arithmetic type, pointer or void",
+            token_list,
+        );
+    }
+    if !token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
+        invalid_token_exit("function pointer declarator requires '('", token_list);
+    }
+    // (void)は引数なしとして扱う
+    token_list.consume_void_arguments();
+    let mut arg_types = vec![];
+    while !token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
+        let type_pos = match token_list.peek_head() {
+            Some(token) => token.token_pos,
+            None => invalid_token_exit("function argument is not coorect", token_list),
+        };
+        let mut ty = pop_type_name(token_list, function_info.as_deref_mut(), program_info);
+        if token_list.is_parentheses(ParenthesesKind::LeftParentheses) || token_list.is_identifier()
+        {
+            ty = pop_variable_name(token_list, ty, function_info.as_deref_mut(), program_info).0;
+        }
+        if ty.is_struct() {
+            error_exit("struct argument is not supported", type_pos);
+        }
+        if let Type::Array(element_ty, _) = ty {
+            ty = Type::pointer_to(*element_ty);
+        }
+        arg_types.push(ty);
+        if !token_list.consume_commma()
+            && !token_list.is_parentheses(ParenthesesKind::RightParentheses)
+        {
+            invalid_token_exit("function argument is not coorect", token_list);
+        }
+    }
+    Type::function(return_ty, arg_types)
 }
 
 // ASTはstmt単位で作成し,
@@ -538,65 +2553,215 @@ pub struct FunctionAST {
     pub function_info: FuntionInfo,
 }
 
-fn pop_function_info(token_list: &mut TokenList) -> FuntionInfo {
-    if let Some(function_name) = token_list.consume_function_definition() {
-        if token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
-            let mut args_count = 0;
-            loop {
+// 関数名に続く仮引数の並びを取り出す
+// プロトタイプ宣言では仮引数名を省略できるが, 定義かどうかは")"の後で決まるので,
+// 最初に省略された仮引数の位置も返す
+fn pop_function_info(
+    token_list: &mut TokenList,
+    function_name: String,
+    return_ty: Type,
+    program_info: &mut ProgramInfo,
+) -> (FuntionInfo, Option<usize>) {
+    if token_list.comsume_parentheses(ParenthesesKind::LeftParentheses) {
+        let mut function_info = FuntionInfo {
+            function_name,
+            return_ty,
+            args: vec![],
+            local_stack_size: 0,
+            current_stack_size: 0,
+            scopes: vec![],
+            switch_infos: vec![],
+            labels: vec![],
+            goto_labels: vec![],
+        };
+        // 引数のスコープ
+        function_info.enter_scope();
+        let mut unnamed_arg_pos = None;
+        // f(void)は引数なしとして扱う
+        token_list.consume_void_arguments();
+        loop {
+            if token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
+                break;
+            }
+            let type_pos = match token_list.peek_head() {
+                Some(token) => token.token_pos,
+                None => invalid_token_exit("function argument is not coorect", token_list),
+            };
+            let ty = pop_type_name(token_list, Some(&mut function_info), program_info);
+            let is_unnamed = token_list.is_commma()
+                || token_list.is_parentheses(ParenthesesKind::RightParentheses);
+            let (mut ty, identifier, identifier_pos) = if is_unnamed {
+                (ty, String::new(), type_pos)
+            } else {
+                pop_variable_name(token_list, ty, Some(&mut function_info), program_info)
+            };
+            if ty.is_struct() {
+                error_exit("struct argument is not supported", identifier_pos);
+            }
+            // 配列型の引数はポインタとして受け取る
+            if let Type::Array(element_ty, _) = ty {
+                ty = Type::pointer_to(*element_ty);
+            }
+            if is_unnamed {
+                // 名前のない引数は参照できないので, 型だけを記録してスタックには割り当てない
+                if ty.is_incomplete() {
+                    error_exit("argument has incomplete type", identifier_pos);
+                }
+                unnamed_arg_pos.get_or_insert(identifier_pos);
+                function_info.args.push(LocalVariable {
+                    name: identifier,
+                    ty,
+                    offset: 0,
+                });
+            } else {
+                let arg = function_info.add_local_variable(identifier, ty, identifier_pos);
+                function_info.args.push(arg);
+            }
+            if token_list.consume_commma() {
+                continue;
+            } else {
                 if token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
                     break;
-                }
-                token_list.expect_variable_definition();
-                args_count += 1;
-                if args_count == 7 {
-                    invalid_token_exit("too many argument", token_list);
-                }
-                if token_list.consume_commma() {
-                    continue;
                 } else {
-                    if token_list.comsume_parentheses(ParenthesesKind::RightParentheses) {
-                        break;
-                    } else {
-                        invalid_token_exit("function argument is not coorect", token_list);
-                    }
+                    invalid_token_exit("function argument is not coorect", token_list);
                 }
             }
-            let function_info = FuntionInfo {
-                function_name,
-                args_count,
-                local_stack_size: token_list.local_stack_size,
-            };
-            function_info
-        } else {
-            invalid_token_exit("function  definition requires '('", token_list);
         }
+        (function_info, unnamed_arg_pos)
     } else {
-        invalid_token_exit("invalid function defition", token_list);
+        invalid_token_exit("function  definition requires '('", token_list);
     }
 }
 
 impl FunctionAST {
-    fn new(token_list: &mut TokenList) -> FunctionAST {
-        let function_info: FuntionInfo = pop_function_info(token_list);
-        let function_ast: AST = AST::new(token_list);
+    fn new(
+        token_list: &mut TokenList,
+        mut function_info: FuntionInfo,
+        program_info: &mut ProgramInfo,
+    ) -> FunctionAST {
+        if !token_list.is_braces(BracesKind::LeftBraces) {
+            invalid_token_exit("function body must start with '{'", token_list);
+        }
+        // 関数本体は複文{}なのでASTは1つ
+        let function_ast: AST = AST::new(token_list, &mut function_info, program_info);
+        function_info.check_goto_labels();
 
-        let fucntion_ast = FunctionAST {
+        FunctionAST {
             function_ast,
             function_info,
+        }
+    }
+}
+
+pub struct ProgramAST {
+    pub function_asts: Vec<FunctionAST>,
+    pub program_info: ProgramInfo,
+}
+
+impl ProgramAST {
+    // program = (function | global_variable)*
+    pub fn make_program_ast(token_list: &mut TokenList) -> ProgramAST {
+        let mut program_info = ProgramInfo {
+            global_variables: vec![],
+            struct_tags: vec![],
+            functions: vec![],
+            unresolved_calls: vec![],
         };
+        let mut function_asts = vec![];
 
-        // 関数は複文{}なのでASTは1つなのでここには来ないはず
-        if !token_list.is_empty() {
-            error_exit(
-                "some error happened",
-                token_list.pop_head().unwrap().token_pos,
-            );
+        while let Some(head_token) = token_list.peek_head() {
+            let type_pos = head_token.token_pos;
+            let ty = pop_type_name(token_list, None, &mut program_info);
+            // struct point { ... }; のように構造体の型だけを宣言する場合
+            if ty.is_struct() && token_list.is_statement_end() {
+                token_list.consume_statement_end();
+                continue;
+            }
+            // 型名の次が関数名の場合は関数定義かプロトタイプ宣言
+            let name_pos = token_list.peek_head().as_ref().map(|token| token.token_pos);
+            if let Some(function_name) = token_list.consume_functioncall() {
+                if !ty.is_arithmetic() && !ty.is_pointer() && ty != Type::Void {
+                    error_exit(
+                        "function must return arithmetic type, pointer or void",
+                        type_pos,
+                    );
+                }
+                let name_pos = name_pos.unwrap();
+                let (function_info, unnamed_arg_pos) =
+                    pop_function_info(token_list, function_name, ty, &mut program_info);
+                if token_list.is_statement_end() {
+                    token_list.consume_statement_end();
+                    program_info.declare_function(&function_info, false, name_pos);
+                    continue;
+                }
+                if let Some(unnamed_arg_pos) = unnamed_arg_pos {
+                    error_exit(
+                        "argument name omitted in function definition",
+                        unnamed_arg_pos,
+                    );
+                }
+                // 再帰呼び出しのため, 本体の解析前に登録する
+                program_info.declare_function(&function_info, true, name_pos);
+                function_asts.push(FunctionAST::new(
+                    token_list,
+                    function_info,
+                    &mut program_info,
+                ));
+            } else {
+                let (ty, identifier, identifier_pos) =
+                    pop_variable_name(token_list, ty, None, &mut program_info);
+                let initial_value = pop_global_initializer(token_list, &ty);
+                token_list.consume_statement_end();
+                let global_variable = GlobalVariable {
+                    name: identifier,
+                    ty,
+                    initial_value,
+                };
+                program_info.add_global_variable(global_variable, identifier_pos);
+            }
+        }
+        program_info.check_unresolved_calls();
+
+        ProgramAST {
+            function_asts,
+            program_info,
         }
-        fucntion_ast
     }
+}
 
-    pub fn make_function_ast(token_list: &mut TokenList) -> FunctionAST {
-        let fucntion_ast = FunctionAST::new(token_list);
-        fucntion_ast
+// グローバル変数の初期値を取り出す
+// ("=" "-"? (num | float_num))?
+// 整数の変数は型に合わせて切り詰めた値, 浮動小数点数の変数はメモリに置くビット列を返す
+fn pop_global_initializer(token_list: &mut TokenList, ty: &Type) -> Option<i64> {
+    if !token_list.is_assign() {
+        return None;
+    }
+    let assign_token = token_list.pop_head().unwrap();
+    // 配列と構造体に1つの値だけを書くとサイズの足りないデータになるので受け付けない
+    if ty.is_array() {
+        error_exit("array initializer is not supported", assign_token.token_pos);
+    }
+    if ty.is_struct() {
+        error_exit(
+            "struct initializer is not supported",
+            assign_token.token_pos,
+        );
+    }
+    let sign = if token_list.consume_operation(OperationKind::Sub) {
+        -1
+    } else {
+        1
+    };
+    if let Some((value, _)) = token_list.consume_float_literal() {
+        let value = sign as f64 * value;
+        if ty.is_float() {
+            return Some(ty.float_bits(value));
+        }
+        return Some(ty.convert_constant(value as i64));
+    }
+    match token_list.consume_number() {
+        Some(num) if ty.is_float() => Some(ty.float_bits((sign * num) as f64)),
+        Some(num) => Some(ty.convert_constant(sign * num)),
+        None => invalid_token_exit("global initializer must be number", token_list),
     }
 }
@@ -1,30 +1,55 @@
 use super::tokenizer::OperationKind;
-use crate::ast::{ASTNode, ASTNodeKind, FunctionAST, FuntionInfo, PrimaryNodeKind, AST};
-use crate::error::{error_exit};
+use crate::ast::{
+    ASTNode, ASTNodeKind, FunctionAST, FuntionInfo, PrimaryNodeKind, ProgramInfo, AST,
+};
+use crate::error::error_exit;
+use crate::types::{align_to, Type};
 
-struct Instructions {
-    vec: Vec<String>,
+const ARG_REGISTERS_64: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const ARG_REGISTERS_32: [&str; 6] = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
+const ARG_REGISTERS_16: [&str; 6] = ["di", "si", "dx", "cx", "r8w", "r9w"];
+const ARG_REGISTERS_8: [&str; 6] = ["dil", "sil", "dl", "cl", "r8b", "r9b"];
+// 浮動小数点数の引数を渡すレジスタの数, xmm0から順に使う
+const FLOAT_ARG_REGISTERS_COUNT: usize = 8;
+
+// ラベル番号が関数間で重複しないように, プログラム全体で1つ使う
+pub struct Instructions {
+    pub vec: Vec<String>,
     end_count: usize,
     else_count: usize,
     begin_count: usize,
+    logic_count: usize,
+    // 内側のループから順に積んだbreak, continueの飛び先ラベル
+    break_labels: Vec<String>,
+    continue_labels: Vec<String>,
+    // caseのラベルに使う, 内側のswitchのラベル番号が末尾
+    switch_count: usize,
+    switch_counts: Vec<usize>,
+    // gotoのラベルを関数ごとに分けるため, コンパイル中の関数名を持つ
+    function_name: String,
+    // .rodataに出力する文字列リテラル, 添字が.LCのラベル番号
+    string_literals: Vec<Vec<u8>>,
 }
 
 impl Instructions {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Instructions {
             vec: vec![],
             end_count: 0,
             else_count: 0,
             begin_count: 0,
+            logic_count: 0,
+            break_labels: vec![],
+            continue_labels: vec![],
+            switch_count: 0,
+            switch_counts: vec![],
+            function_name: String::new(),
+            string_literals: vec![],
         }
     }
 
-    fn push(&mut self, instruction: String) {
-        self.vec.push(instruction)
-    }
-
-    fn pop(&mut self) {
-        self.vec.pop();
+    fn push<S: Into<String>>(&mut self, instruction: S) {
+        self.vec.push(instruction.into())
     }
 
     fn end_count_up(&mut self) {
@@ -38,304 +63,1091 @@ impl Instructions {
     fn begin_count_up(&mut self) {
         self.begin_count += 1;
     }
+
+    fn logic_count_up(&mut self) {
+        self.logic_count += 1;
+    }
+
+    // ループ本体をコンパイルする間だけbreak, continueの飛び先を積む
+    fn push_loop_labels(&mut self, break_label: String, continue_label: String) {
+        self.break_labels.push(break_label);
+        self.continue_labels.push(continue_label);
+    }
+
+    fn pop_loop_labels(&mut self) {
+        self.break_labels.pop();
+        self.continue_labels.pop();
+    }
+
+    // 文字列リテラルを登録してラベル番号を返す
+    fn add_string_literal(&mut self, bytes: Vec<u8>) -> usize {
+        self.string_literals.push(bytes);
+        self.string_literals.len() - 1
+    }
 }
 
 // ローカル変数のアドレスをスタックにpushする
 fn push_local_variable_address(offset: usize, instructions: &mut Instructions) {
-    instructions.push(format!("    mov rax, rbp"));
+    instructions.push("    mov rax, rbp");
     instructions.push(format!("    sub rax, {}", offset));
-    instructions.push(format!("    push rax"));
+    instructions.push("    push rax");
 }
 
-fn push_left_value_adress(mut node: ASTNode, instructions: &mut Instructions, dereference_pos: usize){
-    let pointer_to_node = node.left.take().unwrap();
-    if let  ASTNodeKind::Primary(PrimaryNodeKind::LocalVariable(offset)) = pointer_to_node.node_kind  {
-        // 変数値
+fn push_global_variable_address(name: &str, instructions: &mut Instructions) {
+    instructions.push(format!("    lea rax, [rip + {}]", name));
+    instructions.push("    push rax");
+}
+
+// プログラム中のラベルのアセンブラ上の名前
+// 識別子は.を含まないので, Instructionsが作る.Lbegin0などのラベルとは重ならない
+fn user_label(function_name: &str, label_name: &str) -> String {
+    format!(".L.{}.{}", function_name, label_name)
+}
+
+// 左辺値のアドレスをスタックにpushする
+fn push_left_value_adress(mut node: ASTNode, instructions: &mut Instructions, text_pos: usize) {
+    if let ASTNodeKind::Primary(PrimaryNodeKind::LocalVariable(offset)) = node.node_kind {
         push_local_variable_address(offset, instructions);
-            
-    } else if let ASTNodeKind::Dereference(text_pos) = pointer_to_node.node_kind {
-        push_left_value_adress(*pointer_to_node, instructions, text_pos);
+    } else if let ASTNodeKind::Primary(PrimaryNodeKind::GlobalVariable(name)) = node.node_kind {
+        push_global_variable_address(&name, instructions);
+    } else if let ASTNodeKind::Dereference(_) = node.node_kind {
+        // *pのアドレスはpの値
+        let pointer_node = node.left.take().unwrap();
+        compile_node(*pointer_node, instructions);
+    } else if let ASTNodeKind::Member(offset) = node.node_kind {
+        push_member_address(node, offset, instructions);
+    } else {
+        error_exit("left value cannot do operation", text_pos);
+    }
+}
+
+// 構造体型の式の値は構造体の先頭アドレスなので, それにメンバのオフセットを足す
+fn push_member_address(mut node: ASTNode, offset: usize, instructions: &mut Instructions) {
+    let struct_node = node.left.take().unwrap();
+    compile_node(*struct_node, instructions);
+    instructions.push("    pop rax");
+    instructions.push(format!("    add rax, {}", offset));
+    instructions.push("    push rax");
+}
+
+// スタック先頭のアドレスから型のサイズ分の値を読み込んでpushする
+fn load(ty: &Type, instructions: &mut Instructions) {
+    // 配列と構造体は先頭アドレス, 関数は関数のアドレスをそのまま値として扱う
+    if ty.is_array() || ty.is_struct() || ty.is_function() {
+        return;
+    }
+    instructions.push("    pop rax");
+    // スタック上の値は常に型に合わせて64bitに拡張しておく
+    let is_unsigned = ty.is_unsigned();
+    if ty.size() == 1 && is_unsigned {
+        instructions.push("    movzx rax, byte ptr [rax]");
+    } else if ty.size() == 1 {
+        instructions.push("    movsx rax, byte ptr [rax]");
+    } else if ty.size() == 2 && is_unsigned {
+        instructions.push("    movzx rax, word ptr [rax]");
+    } else if ty.size() == 2 {
+        instructions.push("    movsx rax, word ptr [rax]");
+    } else if ty.size() == 4 && (is_unsigned || ty.is_float()) {
+        // floatはビット列なので上位32bitは0にしておく
+        instructions.push("    mov eax, dword ptr [rax]");
+    } else if ty.size() == 4 {
+        instructions.push("    movsxd rax, dword ptr [rax]");
+    } else {
+        instructions.push("    mov rax, [rax]");
+    }
+    instructions.push("    push rax");
+}
+
+// 64bitのレジスタの値を型のサイズに切り詰め, 符号に合わせて64bitに拡張し直す
+// registerはraxかrdi
+fn extend_register(ty: &Type, register: &str, instructions: &mut Instructions) {
+    let (register_8, register_16, register_32) = if register == "rax" {
+        ("al", "ax", "eax")
+    } else {
+        ("dil", "di", "edi")
+    };
+    match ty {
+        Type::Char => instructions.push(format!("    movsx {}, {}", register, register_8)),
+        Type::UChar => instructions.push(format!("    movzx {}, {}", register, register_8)),
+        Type::Short => instructions.push(format!("    movsx {}, {}", register, register_16)),
+        Type::UShort => instructions.push(format!("    movzx {}, {}", register, register_16)),
+        Type::Int => instructions.push(format!("    movsxd {}, {}", register, register_32)),
+        // 32bitレジスタへの書き込みで上位32bitは0になる
+        Type::UInt => instructions.push(format!("    mov {}, {}", register_32, register_32)),
+        _ => {}
+    }
+}
+
+// スタック先頭の値をfromの型からtoの型に変換する
+// 64bitの整数型への整数の変換は値が既に拡張されているので何もしない
+fn convert_stack_top(from: &Type, to: &Type, instructions: &mut Instructions) {
+    if from == to {
+        return;
+    }
+    if from.is_float() || to.is_float() {
+        instructions.push("    pop rax");
+        convert_float_register(from, to, instructions);
+        instructions.push("    push rax");
+        return;
+    }
+    if !to.is_integer() || to.size() == 8 {
+        return;
+    }
+    instructions.push("    pop rax");
+    extend_register(to, "rax", instructions);
+    instructions.push("    push rax");
+}
+
+// raxの値を浮動小数点数を含む型の間で変換する, xmm0を使う
+// unsigned longの2^63以上の値はlongとして変換する
+fn convert_float_register(from: &Type, to: &Type, instructions: &mut Instructions) {
+    let from_suffix = float_suffix(from);
+    let to_suffix = float_suffix(to);
+    if from.is_float() && to.is_float() {
+        instructions.push("    movq xmm0, rax");
+        instructions.push(format!("    cvt{}2{} xmm0, xmm0", from_suffix, to_suffix));
+        push_float_result(to, instructions);
+    } else if from.is_float() {
+        // 小数点以下は0の方向に切り捨てる
+        instructions.push("    movq xmm0, rax");
+        instructions.push(format!("    cvtt{}2si rax, xmm0", from_suffix));
+        extend_register(to, "rax", instructions);
+    } else {
+        instructions.push(format!("    cvtsi2{} xmm0, rax", to_suffix));
+        push_float_result(to, instructions);
+    }
+}
+
+// SSE命令の型を表す接尾辞, floatはss, doubleはsd
+fn float_suffix(ty: &Type) -> &'static str {
+    if *ty == Type::Float {
+        "ss"
+    } else {
+        "sd"
+    }
+}
+
+// xmm0の浮動小数点数をraxに移す, floatの上位32bitは0にする
+fn push_float_result(ty: &Type, instructions: &mut Instructions) {
+    if *ty == Type::Float {
+        instructions.push("    movd eax, xmm0");
+    } else {
+        instructions.push("    movq rax, xmm0");
+    }
+}
+
+// 二項演算を行う型, シフトは左辺の型で行う
+// ポインタ演算ではポインタを符号なし64bitとして扱う
+fn operation_type(operation: &OperationKind, left_ty: &Type, right_ty: &Type) -> Type {
+    match operation {
+        OperationKind::Shl | OperationKind::Shr => left_ty.promoted(),
+        _ => Type::common_type(left_ty, right_ty),
+    }
+}
+
+// スタック先頭の値をその次のアドレスに書き込み, 値をpushし直す
+fn store(ty: &Type, instructions: &mut Instructions) {
+    instructions.push("    pop rdi");
+    instructions.push("    pop rax");
+    if ty.is_struct() {
+        // 構造体の値はコピー元のアドレスなので1バイトずつコピーする
+        for i in 0..ty.size() {
+            instructions.push(format!("    mov r8b, [rdi + {}]", i));
+            instructions.push(format!("    mov [rax + {}], r8b", i));
+        }
+    } else if ty.size() == 1 {
+        instructions.push("    mov [rax], dil");
+    } else if ty.size() == 2 {
+        instructions.push("    mov [rax], di");
+    } else if ty.size() == 4 {
+        instructions.push("    mov [rax], edi");
     } else {
-        error_exit("left value cannot do operation", dereference_pos);
+        instructions.push("    mov [rax], rdi");
     }
-    instructions.push(format!("    pop rax"));
-    instructions.push(format!("    mov rax, [rax]"));
-    instructions.push(format!("    push rax"));
+    instructions.push("    push rdi");
+}
 
+// 条件式を評価して, 真偽を判定できる値をpushする
+// 浮動小数点数は-0.0も偽になるよう, ビット列ではなく0.0と比べた結果にする
+fn compile_condition(node: ASTNode, instructions: &mut Instructions) {
+    let ty = node.ty.clone();
+    compile_node(node, instructions);
+    if let Some(ty) = ty.filter(|ty| ty.is_float()) {
+        instructions.push("    pop rax");
+        instructions.push("    xor edi, edi");
+        compile_float_operation(&OperationKind::Not, &ty, instructions);
+    }
 }
 
 fn compile_node(mut node: ASTNode, instructions: &mut Instructions) {
     if let ASTNodeKind::Primary(PrimaryNodeKind::Number(num)) = node.node_kind {
-        let instruction = format!("    push {}", num);
-        instructions.push(instruction);
+        // pushの即値は32bitまでなので, それ以上の値はraxを経由する
+        if (i32::MIN as i64..=i32::MAX as i64).contains(&num) {
+            instructions.push(format!("    push {}", num));
+        } else {
+            instructions.push(format!("    mov rax, {}", num));
+            instructions.push("    push rax");
+        }
+        return;
+    } else if let ASTNodeKind::Primary(PrimaryNodeKind::FloatNumber(bits)) = node.node_kind {
+        // 浮動小数点数はビット列を即値として積む
+        let bits = node.ty.as_ref().unwrap().float_bits(f64::from_bits(bits));
+        instructions.push(format!("    mov rax, {}", bits));
+        instructions.push("    push rax");
         return;
     } else if let ASTNodeKind::Primary(PrimaryNodeKind::LocalVariable(offset)) = node.node_kind {
         push_local_variable_address(offset, instructions);
-        instructions.push(format!("    pop rax"));
-        instructions.push(format!("    mov rax, [rax]"));
-        instructions.push(format!("    push rax"));
+        load(node.ty.as_ref().unwrap(), instructions);
+        return;
+    } else if let ASTNodeKind::Primary(PrimaryNodeKind::GlobalVariable(name)) = node.node_kind {
+        push_global_variable_address(&name, instructions);
+        load(node.ty.as_ref().unwrap(), instructions);
+        return;
+    } else if let ASTNodeKind::Primary(PrimaryNodeKind::FunctionAddress(name)) = node.node_kind {
+        // 他の翻訳単位の関数も指せるように, GOT経由でアドレスを読む
+        instructions.push(format!("    mov rax, qword ptr [rip + {}@GOTPCREL]", name));
+        instructions.push("    push rax");
+        return;
+    } else if let ASTNodeKind::Primary(PrimaryNodeKind::StringLiteral(bytes)) = node.node_kind {
+        // 文字列リテラルは.rodataに置いたラベルのアドレスになる
+        let label_count = instructions.add_string_literal(bytes);
+        instructions.push(format!("    lea rax, [rip + .LC{}]", label_count));
+        instructions.push("    push rax");
         return;
     } else if let ASTNodeKind::Assign(text_pos) = node.node_kind {
-        //  = の左辺値が変数であること
+        // 左辺値のアドレスに右辺の値を書き込む
         // 渡されたastは正しいのでunwrapしても問題ない
         let left_node = node.left.take().unwrap();
         let right_node = node.right.take().unwrap();
-        if let ASTNodeKind::Primary(PrimaryNodeKind::LocalVariable(offset)) = left_node.node_kind {
-            push_local_variable_address(offset, instructions);
-            compile_node(*right_node, instructions);
-            instructions.push(format!("    pop rdi"));
-            instructions.push(format!("    pop rax"));
-            instructions.push(format!("    mov [rax], rdi"));
-            instructions.push(format!("    push rdi"));
-            return;
-        } else if let ASTNodeKind::Dereference(text_pos) = left_node.node_kind {
-            push_left_value_adress(*left_node, instructions, text_pos);
-            compile_node(*right_node, instructions);
-            instructions.push(format!("    pop rdi"));
-            instructions.push(format!("    pop rax"));
-            instructions.push(format!("    mov [rax], rdi"));
-            instructions.push(format!("    push rdi"));
-            return;
-        } else {
-            error_exit("left value is not correct", text_pos);
-        }
-    } else if let ASTNodeKind::Return = node.node_kind {
+        push_left_value_adress(*left_node, instructions, text_pos);
+        compile_node(*right_node, instructions);
+        store(node.ty.as_ref().unwrap(), instructions);
+        return;
+    } else if let ASTNodeKind::CompoundAssign(operation, text_pos) = node.node_kind {
+        // 左辺値のアドレスは1度だけ評価し, 複製したものから値を読む
         let left_node = node.left.take().unwrap();
-        compile_node(*left_node, instructions);
-        instructions.push(format!("    pop rax"));
-        instructions.push(format!("    mov rsp, rbp"));
-        instructions.push(format!("    pop rbp"));
-        instructions.push(format!("    ret"));
+        let right_node = node.right.take().unwrap();
+        let ty = node.ty.clone().unwrap();
+        let right_ty = right_node.ty.clone().unwrap();
+        let left_ty = compound_left_type(&operation, &ty, &right_ty);
+        push_left_value_adress(*left_node, instructions, text_pos);
+        instructions.push("    push qword ptr [rsp]");
+        load(&ty, instructions);
+        convert_stack_top(&ty, &left_ty, instructions);
+        compile_node(*right_node, instructions);
+        compile_operation(&operation, &left_ty, &right_ty, instructions);
+        convert_stack_top(&left_ty, &ty, instructions);
+        store(&ty, instructions);
+        return;
+    } else if let ASTNodeKind::PostfixAssign(operation, text_pos) = node.node_kind {
+        // 演算前の値を残すため, スタックを [演算前の値, アドレス, 演算前の値] にしてから演算する
+        let left_node = node.left.take().unwrap();
+        let right_node = node.right.take().unwrap();
+        let ty = node.ty.clone().unwrap();
+        let right_ty = right_node.ty.clone().unwrap();
+        let left_ty = compound_left_type(&operation, &ty, &right_ty);
+        push_left_value_adress(*left_node, instructions, text_pos);
+        instructions.push("    push qword ptr [rsp]");
+        load(&ty, instructions);
+        instructions.push("    pop rdi");
+        instructions.push("    pop rax");
+        instructions.push("    push rdi");
+        instructions.push("    push rax");
+        instructions.push("    push rdi");
+        convert_stack_top(&ty, &left_ty, instructions);
+        compile_node(*right_node, instructions);
+        compile_operation(&operation, &left_ty, &right_ty, instructions);
+        convert_stack_top(&left_ty, &ty, instructions);
+        store(&ty, instructions);
+        instructions.push("    pop rax");
+        return;
+    } else if let ASTNodeKind::Return = node.node_kind {
+        // voidの関数のreturnは値を持たない
+        // 浮動小数点数の戻り値はxmm0で返す, 値は関数の戻り値の型に変換済み
+        if let Some(left_node) = node.left.take() {
+            let is_float = left_node.ty.as_ref().unwrap().is_float();
+            compile_node(*left_node, instructions);
+            instructions.push("    pop rax");
+            if is_float {
+                instructions.push("    movq xmm0, rax");
+            }
+        }
+        instructions.push("    mov rsp, rbp");
+        instructions.push("    pop rbp");
+        instructions.push("    ret");
+        return;
+    } else if let ASTNodeKind::ExprStmt = node.node_kind {
+        // 式の値は使わないので捨てる
+        let expr_node = node.left.take().unwrap();
+        compile_node(*expr_node, instructions);
+        instructions.push("    pop rax");
         return;
     } else if let ASTNodeKind::If = node.node_kind {
-        let condition_node = node.left.take().unwrap();
-        let instruction_node = node.right.take().unwrap();
-        compile_node(*condition_node, instructions);
-        instructions.push(format!("    pop rax"));
-        instructions.push(format!("    cmp rax, 0"));
-        instructions.push(format!("    je .Lend{}", instructions.end_count));
-        compile_node(*instruction_node, instructions);
-        instructions.push(format!(".Lend{}:", instructions.end_count));
+        // ネストした文とラベルが被らないように, 先にラベル番号を確保する
+        let end_count = instructions.end_count;
         instructions.end_count_up();
+        let condition_node = node.left.take().unwrap();
+        compile_condition(*condition_node, instructions);
+        instructions.push("    pop rax");
+        instructions.push("    cmp rax, 0");
+        instructions.push(format!("    je .Lend{}", end_count));
+        if let Some(instruction_node) = node.right.take() {
+            compile_node(*instruction_node, instructions);
+        }
+        instructions.push(format!(".Lend{}:", end_count));
         return;
     } else if let ASTNodeKind::IfElse = node.node_kind {
+        let end_count = instructions.end_count;
+        let else_count = instructions.else_count;
+        instructions.end_count_up();
+        instructions.else_count_up();
         let condition_node = node.left.take().unwrap();
-        let instruction_node = node.right.take().unwrap();
-        compile_node(*condition_node, instructions);
-        instructions.push(format!("    pop rax"));
-        instructions.push(format!("    cmp rax, 0"));
-        instructions.push(format!("    je .Lelse{}", instructions.else_count));
-        compile_node(*instruction_node, instructions);
-        instructions.push(format!("    jmp .Lend{}", instructions.end_count));
-        instructions.push(format!(".Lelse{}:", instructions.end_count));
+        compile_condition(*condition_node, instructions);
+        instructions.push("    pop rax");
+        instructions.push("    cmp rax, 0");
+        instructions.push(format!("    je .Lelse{}", else_count));
+        if let Some(instruction_node) = node.right.take() {
+            compile_node(*instruction_node, instructions);
+        }
+        instructions.push(format!("    jmp .Lend{}", end_count));
+        instructions.push(format!(".Lelse{}:", else_count));
         // elseが付属するifの場合, if(A) B else C
         // のCは node.vec[0]にある.
         // AST構築の段階でelse文のチェックをしているのでunwrapして良い
         let mut else_vec = node.vec.take().unwrap();
-        let else_instruction_node = else_vec[0].take().unwrap();
-        compile_node(*else_instruction_node, instructions);
-        instructions.push(format!(".Lend{}:", instructions.end_count));
+        if let Some(else_instruction_node) = else_vec[0].take() {
+            compile_node(*else_instruction_node, instructions);
+        }
+        instructions.push(format!(".Lend{}:", end_count));
+        return;
+    } else if let ASTNodeKind::Conditional(_) = node.node_kind {
+        // どちらの分岐でも値を1つだけpushする
+        let end_count = instructions.end_count;
+        let else_count = instructions.else_count;
         instructions.end_count_up();
         instructions.else_count_up();
+        let condition_node = node.left.take().unwrap();
+        compile_condition(*condition_node, instructions);
+        instructions.push("    pop rax");
+        instructions.push("    cmp rax, 0");
+        instructions.push(format!("    je .Lelse{}", else_count));
+        let then_node = node.right.take().unwrap();
+        compile_node(*then_node, instructions);
+        instructions.push(format!("    jmp .Lend{}", end_count));
+        instructions.push(format!(".Lelse{}:", else_count));
+        let else_node = node.vec.take().unwrap()[0].take().unwrap();
+        compile_node(*else_node, instructions);
+        instructions.push(format!(".Lend{}:", end_count));
         return;
     } else if let ASTNodeKind::While = node.node_kind {
-        let condition_node = node.left.take().unwrap();
-        let instruction_node = node.right.take().unwrap();
-        instructions.push(format!(".Lbegin{}:", instructions.begin_count));
-        compile_node(*condition_node, instructions);
-        instructions.push(format!("    pop rax"));
-        instructions.push(format!("    cmp rax, 0"));
-        instructions.push(format!("    je .Lend{}", instructions.else_count));
-        compile_node(*instruction_node, instructions);
-        instructions.push(format!("    jmp .Lbegin{}", instructions.begin_count));
-        instructions.push(format!(".Lend{}:", instructions.end_count));
+        let begin_count = instructions.begin_count;
+        let end_count = instructions.end_count;
+        instructions.begin_count_up();
         instructions.end_count_up();
+        let condition_node = node.left.take().unwrap();
+        instructions.push(format!(".Lbegin{}:", begin_count));
+        compile_condition(*condition_node, instructions);
+        instructions.push("    pop rax");
+        instructions.push("    cmp rax, 0");
+        instructions.push(format!("    je .Lend{}", end_count));
+        if let Some(instruction_node) = node.right.take() {
+            instructions.push_loop_labels(
+                format!(".Lend{}", end_count),
+                format!(".Lbegin{}", begin_count),
+            );
+            compile_node(*instruction_node, instructions);
+            instructions.pop_loop_labels();
+        }
+        instructions.push(format!("    jmp .Lbegin{}", begin_count));
+        instructions.push(format!(".Lend{}:", end_count));
+        return;
+    } else if let ASTNodeKind::DoWhile = node.node_kind {
+        let begin_count = instructions.begin_count;
+        let end_count = instructions.end_count;
         instructions.begin_count_up();
+        instructions.end_count_up();
+        instructions.push(format!(".Lbegin{}:", begin_count));
+        if let Some(instruction_node) = node.left.take() {
+            // continueは判定式に飛ぶ
+            instructions.push_loop_labels(
+                format!(".Lend{}", end_count),
+                format!(".Lcontinue{}", begin_count),
+            );
+            compile_node(*instruction_node, instructions);
+            instructions.pop_loop_labels();
+        }
+        instructions.push(format!(".Lcontinue{}:", begin_count));
+        let condition_node = node.right.take().unwrap();
+        compile_condition(*condition_node, instructions);
+        instructions.push("    pop rax");
+        instructions.push("    cmp rax, 0");
+        instructions.push(format!("    jne .Lbegin{}", begin_count));
+        instructions.push(format!(".Lend{}:", end_count));
+        return;
+    } else if let ASTNodeKind::Switch(case_values, has_default) = node.node_kind {
+        let switch_count = instructions.switch_count;
+        let end_count = instructions.end_count;
+        instructions.switch_count += 1;
+        instructions.end_count_up();
+        let condition_node = node.left.take().unwrap();
+        compile_node(*condition_node, instructions);
+        instructions.push("    pop rax");
+        for (case_index, value) in case_values.iter().enumerate() {
+            instructions.push(format!("    mov rdi, {}", value));
+            instructions.push("    cmp rax, rdi");
+            instructions.push(format!("    je .Lcase{}_{}", switch_count, case_index));
+        }
+        if has_default {
+            instructions.push(format!("    jmp .Ldefault{}", switch_count));
+        } else {
+            instructions.push(format!("    jmp .Lend{}", end_count));
+        }
+        if let Some(body_node) = node.right.take() {
+            // switch内のcontinueは外側のループに飛ぶので, breakの飛び先だけ積む
+            instructions
+                .break_labels
+                .push(format!(".Lend{}", end_count));
+            instructions.switch_counts.push(switch_count);
+            compile_node(*body_node, instructions);
+            instructions.switch_counts.pop();
+            instructions.break_labels.pop();
+        }
+        instructions.push(format!(".Lend{}:", end_count));
+        return;
+    } else if let ASTNodeKind::Case(case_index) = node.node_kind {
+        // caseはAST側でswitchの中にあることを確認している
+        let switch_count = *instructions.switch_counts.last().unwrap();
+        instructions.push(format!(".Lcase{}_{}:", switch_count, case_index));
+        if let Some(stmt_node) = node.left.take() {
+            compile_node(*stmt_node, instructions);
+        }
+        return;
+    } else if let ASTNodeKind::Default = node.node_kind {
+        let switch_count = *instructions.switch_counts.last().unwrap();
+        instructions.push(format!(".Ldefault{}:", switch_count));
+        if let Some(stmt_node) = node.left.take() {
+            compile_node(*stmt_node, instructions);
+        }
         return;
     } else if let ASTNodeKind::For = node.node_kind {
+        let begin_count = instructions.begin_count;
+        let end_count = instructions.end_count;
+        instructions.begin_count_up();
+        instructions.end_count_up();
         let mut instruction_vec = node.vec.take().unwrap();
-        let loop_instruction = node.left.take().unwrap();
+        // 初期化は式文か宣言なので値は残らない
         if let Some(initial_instruction) = instruction_vec[0].take() {
             compile_node(*initial_instruction, instructions);
         }
-        instructions.push(format!(".Lbegin{}:", instructions.begin_count));
+        instructions.push(format!(".Lbegin{}:", begin_count));
+        // 判定式がない場合は無限ループ
         if let Some(judge_instruction) = instruction_vec[1].take() {
-            compile_node(*judge_instruction, instructions);
+            compile_condition(*judge_instruction, instructions);
+            instructions.push("    pop rax");
+            instructions.push("    cmp rax, 0");
+            instructions.push(format!("    je .Lend{}", end_count));
+        }
+        if let Some(loop_instruction) = node.left.take() {
+            // continueは更新式に飛ぶ
+            instructions.push_loop_labels(
+                format!(".Lend{}", end_count),
+                format!(".Lcontinue{}", begin_count),
+            );
+            compile_node(*loop_instruction, instructions);
+            instructions.pop_loop_labels();
         }
-        instructions.push(format!("    pop rax"));
-        instructions.push(format!("    cmp rax, 0"));
-        instructions.push(format!("    je .Lend{}", instructions.else_count));
-        compile_node(*loop_instruction, instructions);
+        instructions.push(format!(".Lcontinue{}:", begin_count));
         if let Some(update_instruction) = instruction_vec[2].take() {
             compile_node(*update_instruction, instructions);
+            instructions.push("    pop rax");
+        }
+        instructions.push(format!("    jmp .Lbegin{}", begin_count));
+        instructions.push(format!(".Lend{}:", end_count));
+        return;
+    } else if let ASTNodeKind::Break(text_pos) = node.node_kind {
+        match instructions.break_labels.last() {
+            Some(label) => {
+                let instruction = format!("    jmp {}", label);
+                instructions.push(instruction);
+            }
+            None => error_exit("break is not in loop", text_pos),
+        }
+        return;
+    } else if let ASTNodeKind::Continue(text_pos) = node.node_kind {
+        match instructions.continue_labels.last() {
+            Some(label) => {
+                let instruction = format!("    jmp {}", label);
+                instructions.push(instruction);
+            }
+            None => error_exit("continue is not in loop", text_pos),
+        }
+        return;
+    } else if let ASTNodeKind::Goto(label_name) = node.node_kind {
+        let label = user_label(&instructions.function_name, &label_name);
+        instructions.push(format!("    jmp {}", label));
+        return;
+    } else if let ASTNodeKind::Label(label_name) = node.node_kind {
+        let label = user_label(&instructions.function_name, &label_name);
+        instructions.push(format!("{}:", label));
+        if let Some(stmt_node) = node.left.take() {
+            compile_node(*stmt_node, instructions);
         }
-        instructions.push(format!("    pop rax"));
-        instructions.push(format!("    jmp .Lbegin{}", instructions.begin_count));
-        instructions.push(format!(".Lend{}:", instructions.end_count));
-        instructions.end_count_up();
-        instructions.begin_count_up();
         return;
     } else if let ASTNodeKind::MultStmt = node.node_kind {
         // 複文の場合はvecの中に各命令が含まれている
+        // 変数宣言はNoneとして含まれている
         let node_vec = node.vec.unwrap();
-        if node_vec.len() != 0 {
-            for node in node_vec {
-                let node = node.unwrap();
-                compile_node(*node, instructions);
-                instructions.push(format!("    pop rax"));
-            }
-            // 文終了時にpop raxを実行するので,
-            // 複文の最後のpop raxは取り除く
-            instructions.pop();
+        for node in node_vec.into_iter().flatten() {
+            compile_node(*node, instructions);
         }
         return;
     } else if let ASTNodeKind::FunctionCall(function_name) = node.node_kind {
-        let args_vec = node.vec.unwrap();
-        for (arg_counnt, arg) in args_vec.into_iter().enumerate() {
-            compile_node(*arg.unwrap(), instructions);
-            if arg_counnt == 0 {
-                instructions.push(format!("    pop rdi"));
-            } else if arg_counnt == 1 {
-                instructions.push(format!("    pop rsi"));
-            } else if arg_counnt == 2 {
-                instructions.push(format!("    pop rdx"));
-            } else if arg_counnt == 3 {
-                instructions.push(format!("    pop rcx"));
-            } else if arg_counnt == 4 {
-                instructions.push(format!("    pop r8"));
-            } else if arg_counnt == 5 {
-                instructions.push(format!("    pop r9"));
-            }
-        }
-        instructions.push(format!("    call {}", function_name));
-        instructions.push(format!("    push rax"));
+        let return_ty = node.ty.unwrap();
+        compile_call(
+            node.vec.unwrap(),
+            None,
+            &function_name,
+            &return_ty,
+            instructions,
+        );
+        return;
+    } else if let ASTNodeKind::FunctionPointerCall = node.node_kind {
+        let return_ty = node.ty.unwrap();
+        let callee_link = node.left.take();
+        compile_call(
+            node.vec.unwrap(),
+            callee_link,
+            "r10",
+            &return_ty,
+            instructions,
+        );
         return;
     } else if let ASTNodeKind::Reference(text_pos) = node.node_kind {
-        //  &の対象が変数であること
+        //  &の対象が左辺値であること
         // 渡されたastは正しいのでunwrapしても問題ない
         let variable_node = node.left.take().unwrap();
-        if let ASTNodeKind::Primary(PrimaryNodeKind::LocalVariable(offset)) = variable_node.node_kind {
-            push_local_variable_address(offset, instructions);
+        if let ASTNodeKind::Primary(PrimaryNodeKind::LocalVariable(_))
+        | ASTNodeKind::Primary(PrimaryNodeKind::GlobalVariable(_))
+        | ASTNodeKind::Dereference(_)
+        | ASTNodeKind::Member(_) = variable_node.node_kind
+        {
+            push_left_value_adress(*variable_node, instructions, text_pos);
             return;
         } else {
             error_exit("& operarand must be for variable", text_pos);
         }
-    }  else if let ASTNodeKind::Dereference(_text_pos) = node.node_kind {
+    } else if let ASTNodeKind::Dereference(_text_pos) = node.node_kind {
         // ここの*は値であれば良い
         let variable_node = node.left.take().unwrap();
         compile_node(*variable_node, instructions);
-        instructions.push(format!("    pop rax"));
-        instructions.push(format!("    mov rax, [rax]"));
-        instructions.push(format!("    push rax"));
+        load(node.ty.as_ref().unwrap(), instructions);
+        return;
+    } else if let ASTNodeKind::Member(offset) = node.node_kind {
+        let ty = node.ty.clone().unwrap();
+        push_member_address(node, offset, instructions);
+        load(&ty, instructions);
+        return;
+    } else if let ASTNodeKind::Operation(OperationKind::LogicalAnd) = node.node_kind {
+        // 左辺が偽であれば右辺は評価しない
+        let logic_count = instructions.logic_count;
+        instructions.logic_count_up();
+        for operand_node in [node.left.take(), node.right.take()] {
+            compile_condition(*operand_node.unwrap(), instructions);
+            instructions.push("    pop rax");
+            instructions.push("    cmp rax, 0");
+            instructions.push(format!("    je .Lfalse{}", logic_count));
+        }
+        instructions.push("    push 1");
+        instructions.push(format!("    jmp .Llogicend{}", logic_count));
+        instructions.push(format!(".Lfalse{}:", logic_count));
+        instructions.push("    push 0");
+        instructions.push(format!(".Llogicend{}:", logic_count));
+        return;
+    } else if let ASTNodeKind::Operation(OperationKind::LogicalOr) = node.node_kind {
+        // 左辺が真であれば右辺は評価しない
+        let logic_count = instructions.logic_count;
+        instructions.logic_count_up();
+        for operand_node in [node.left.take(), node.right.take()] {
+            compile_condition(*operand_node.unwrap(), instructions);
+            instructions.push("    pop rax");
+            instructions.push("    cmp rax, 0");
+            instructions.push(format!("    jne .Ltrue{}", logic_count));
+        }
+        instructions.push("    push 0");
+        instructions.push(format!("    jmp .Llogicend{}", logic_count));
+        instructions.push(format!(".Ltrue{}:", logic_count));
+        instructions.push("    push 1");
+        instructions.push(format!(".Llogicend{}:", logic_count));
+        return;
+    } else if let ASTNodeKind::Cast = node.node_kind {
+        let operand_node = node.left.take().unwrap();
+        let operand_ty = operand_node.ty.clone().unwrap();
+        compile_node(*operand_node, instructions);
+        convert_stack_top(&operand_ty, node.ty.as_ref().unwrap(), instructions);
+        return;
+    } else if let ASTNodeKind::Negation = node.node_kind {
+        // 符号ビットだけを立てたマスクとのxorで符号を反転する
+        let ty = node.ty.unwrap();
+        let operand_node = node.left.take().unwrap();
+        compile_node(*operand_node, instructions);
+        let sign_mask: u64 = if ty == Type::Float { 1 << 31 } else { 1 << 63 };
+        instructions.push("    pop rax");
+        instructions.push("    movq xmm0, rax");
+        instructions.push(format!("    mov rax, {}", sign_mask));
+        instructions.push("    movq xmm1, rax");
+        instructions.push(format!("    xorp{} xmm0, xmm1", &float_suffix(&ty)[1..]));
+        push_float_result(&ty, instructions);
+        instructions.push("    push rax");
+        return;
+    } else if let ASTNodeKind::Operation(OperationKind::BitNot) = node.node_kind {
+        let operand_node = node.left.take().unwrap();
+        compile_node(*operand_node, instructions);
+        instructions.push("    pop rax");
+        instructions.push("    not rax");
+        extend_register(node.ty.as_ref().unwrap(), "rax", instructions);
+        instructions.push("    push rax");
+        return;
+    } else if let ASTNodeKind::Operation(OperationKind::LogicalNot) = node.node_kind {
+        let operand_node = node.left.take().unwrap();
+        compile_condition(*operand_node, instructions);
+        instructions.push("    pop rax");
+        instructions.push("    cmp rax, 0");
+        instructions.push("    sete al");
+        instructions.push("    movzb rax, al");
+        instructions.push("    push rax");
         return;
     }
 
     // 渡されたastは正しいのでunwrapしても問題ない
     let left_node = node.left.take().unwrap();
     let right_node = node.right.take().unwrap();
+    // ポインタ演算ではポインタの指す型のサイズで整数側をスケールする
+    let left_ty = left_node.ty.clone().unwrap();
+    let right_ty = right_node.ty.clone().unwrap();
     compile_node(*left_node, instructions);
     compile_node(*right_node, instructions);
+    if let ASTNodeKind::Operation(operation) = &node.node_kind {
+        compile_operation(operation, &left_ty, &right_ty, instructions);
+    }
+}
 
-    instructions.push(format!("    pop rdi"));
-    instructions.push(format!("    pop rax"));
+// スタック上の左辺と右辺の値に二項演算を行い, 結果をpushする
+fn compile_operation(
+    operation: &OperationKind,
+    left_ty: &Type,
+    right_ty: &Type,
+    instructions: &mut Instructions,
+) {
+    instructions.push("    pop rdi");
+    instructions.push("    pop rax");
 
-    match node.node_kind {
-        ASTNodeKind::Operation(OperationKind::Add) => {
-            instructions.push(format!("    add rax, rdi"));
+    // 両辺の整数はASTで演算する型に変換済み
+    let ty = operation_type(operation, left_ty, right_ty);
+    if ty.is_float() {
+        compile_float_operation(operation, &ty, instructions);
+        return;
+    }
+
+    match operation {
+        OperationKind::Add => {
+            if let Some(pointee) = left_ty.pointee() {
+                instructions.push(format!("    imul rdi, {}", pointee.size()));
+            } else if let Some(pointee) = right_ty.pointee() {
+                instructions.push(format!("    imul rax, {}", pointee.size()));
+            }
+            instructions.push("    add rax, rdi");
         }
-        ASTNodeKind::Operation(OperationKind::Sub) => {
-            instructions.push(format!("    sub rax, rdi"));
+        OperationKind::Sub => {
+            if let (Some(pointee), true) = (left_ty.pointee(), right_ty.is_pointer()) {
+                // ポインタ同士の差はアドレスの差を要素のサイズで割る
+                instructions.push("    sub rax, rdi");
+                instructions.push(format!("    mov rdi, {}", pointee.size()));
+                instructions.push("    cqo");
+                instructions.push("    idiv rdi");
+            } else {
+                if let Some(pointee) = left_ty.pointee() {
+                    instructions.push(format!("    imul rdi, {}", pointee.size()));
+                }
+                instructions.push("    sub rax, rdi");
+            }
         }
-        ASTNodeKind::Operation(OperationKind::Mul) => {
-            instructions.push(format!("    imul rax, rdi"));
+        OperationKind::Mul => {
+            instructions.push("    imul rax, rdi");
         }
-        ASTNodeKind::Operation(OperationKind::Div) => {
-            instructions.push(format!("    cqo"));
-            instructions.push(format!("    idiv rdi"));
+        OperationKind::Div => {
+            compile_division(&ty, instructions);
         }
-        ASTNodeKind::Operation(OperationKind::Eq) => {
-            instructions.push(format!("    cmp rax, rdi"));
-            instructions.push(format!("    sete al"));
-            instructions.push(format!("    movzb rax, al"));
+        OperationKind::Mod => {
+            // 余りはrdxに入る
+            compile_division(&ty, instructions);
+            instructions.push("    mov rax, rdx");
         }
-        ASTNodeKind::Operation(OperationKind::Not) => {
-            instructions.push(format!("    cmp rax, rdi"));
-            instructions.push(format!("    setne al"));
-            instructions.push(format!("    movzb rax, al"));
+        OperationKind::BitAnd => {
+            instructions.push("    and rax, rdi");
         }
-        // Gt, GeはASTでは左辺値と右辺値を反転させたLt, Leとして形成される
-        ASTNodeKind::Operation(OperationKind::Lt) => {
-            instructions.push(format!("    cmp rax, rdi"));
-            instructions.push(format!("    setl al"));
-            instructions.push(format!("    movzb rax, al"));
+        OperationKind::BitOr => {
+            instructions.push("    or rax, rdi");
+        }
+        OperationKind::BitXor => {
+            instructions.push("    xor rax, rdi");
         }
-        ASTNodeKind::Operation(OperationKind::Le) => {
-            instructions.push(format!("    cmp rax, rdi"));
-            instructions.push(format!("    setle al"));
-            instructions.push(format!("    movzb rax, al"));
+        // シフト量はclで指定する
+        OperationKind::Shl => {
+            instructions.push("    mov rcx, rdi");
+            instructions.push("    sal rax, cl");
+        }
+        OperationKind::Shr => {
+            instructions.push("    mov rcx, rdi");
+            if ty.is_unsigned() {
+                instructions.push("    shr rax, cl");
+            } else {
+                instructions.push("    sar rax, cl");
+            }
+        }
+        OperationKind::Eq => {
+            instructions.push("    cmp rax, rdi");
+            instructions.push("    sete al");
+            instructions.push("    movzb rax, al");
+        }
+        OperationKind::Not => {
+            instructions.push("    cmp rax, rdi");
+            instructions.push("    setne al");
+            instructions.push("    movzb rax, al");
         }
-        // ASTNodeKind::Numberはここには来ない
         // Gt, GeはASTでは左辺値と右辺値を反転させたLt, Leとして形成される
+        OperationKind::Lt => {
+            instructions.push("    cmp rax, rdi");
+            if ty.is_unsigned() {
+                instructions.push("    setb al");
+            } else {
+                instructions.push("    setl al");
+            }
+            instructions.push("    movzb rax, al");
+        }
+        OperationKind::Le => {
+            instructions.push("    cmp rax, rdi");
+            if ty.is_unsigned() {
+                instructions.push("    setbe al");
+            } else {
+                instructions.push("    setle al");
+            }
+            instructions.push("    movzb rax, al");
+        }
+        // 論理演算と単項演算はここには来ない
         _ => {}
     }
-    instructions.push(format!("    push rax"));
+    // 比較の結果は0か1なので, それ以外の演算結果を型に合わせて拡張する
+    if !matches!(
+        operation,
+        OperationKind::Eq | OperationKind::Not | OperationKind::Lt | OperationKind::Le
+    ) {
+        extend_register(&ty, "rax", instructions);
+    }
+    instructions.push("    push rax");
+}
+
+// raxの左辺とrdiの右辺の浮動小数点数に二項演算を行い, 結果をpushする
+fn compile_float_operation(operation: &OperationKind, ty: &Type, instructions: &mut Instructions) {
+    let suffix = float_suffix(ty);
+    instructions.push("    movq xmm0, rax");
+    instructions.push("    movq xmm1, rdi");
+    match operation {
+        OperationKind::Add => instructions.push(format!("    add{} xmm0, xmm1", suffix)),
+        OperationKind::Sub => instructions.push(format!("    sub{} xmm0, xmm1", suffix)),
+        OperationKind::Mul => instructions.push(format!("    mul{} xmm0, xmm1", suffix)),
+        OperationKind::Div => instructions.push(format!("    div{} xmm0, xmm1", suffix)),
+        // NaNとの比較は!=以外が偽になるよう, パリティフラグも見る
+        OperationKind::Eq => {
+            instructions.push(format!("    ucomi{} xmm0, xmm1", suffix));
+            instructions.push("    sete al");
+            instructions.push("    setnp dl");
+            instructions.push("    and al, dl");
+        }
+        OperationKind::Not => {
+            instructions.push(format!("    ucomi{} xmm0, xmm1", suffix));
+            instructions.push("    setne al");
+            instructions.push("    setp dl");
+            instructions.push("    or al, dl");
+        }
+        // a < b は b > a として比べると, NaNの場合に偽になる
+        OperationKind::Lt => {
+            instructions.push(format!("    ucomi{} xmm1, xmm0", suffix));
+            instructions.push("    seta al");
+        }
+        OperationKind::Le => {
+            instructions.push(format!("    ucomi{} xmm1, xmm0", suffix));
+            instructions.push("    setae al");
+        }
+        // 整数だけの演算はASTでエラーにしている
+        _ => {}
+    }
+    if matches!(
+        operation,
+        OperationKind::Eq | OperationKind::Not | OperationKind::Lt | OperationKind::Le
+    ) {
+        instructions.push("    movzb rax, al");
+    } else {
+        push_float_result(ty, instructions);
+    }
+    instructions.push("    push rax");
+}
+
+// 複合代入で演算に使う左辺の型
+// 数値の左辺は読み込んだ後に右辺と揃えた型へ変換する, 右辺はASTで変換済み
+fn compound_left_type(operation: &OperationKind, ty: &Type, right_ty: &Type) -> Type {
+    if ty.is_arithmetic() {
+        operation_type(operation, ty, right_ty)
+    } else {
+        ty.clone()
+    }
+}
+
+// rax / rdi の商をrax, 余りをrdxに求める
+fn compile_division(ty: &Type, instructions: &mut Instructions) {
+    if ty.is_unsigned() {
+        instructions.push("    mov rdx, 0");
+        instructions.push("    div rdi");
+    } else {
+        instructions.push("    cqo");
+        instructions.push("    idiv rdi");
+    }
 }
 
 // astからアセンブラを出力する
 // 渡されるastはrootがNoneか, 正しいASTである
-fn compile_ast(mut ast: AST, instructions: &mut Instructions) {
-    match ast.root.take() {
-        Some(top_node) => {
-            compile_node(*top_node, instructions);
+// 引数を渡して関数を呼び出し, 戻り値をpushする
+// callee_linkがある場合は, その値のアドレスをr10に入れて呼び出す
+fn compile_call(
+    args: Vec<Option<Box<ASTNode>>>,
+    callee_link: Option<Box<ASTNode>>,
+    call_operand: &str,
+    return_ty: &Type,
+    instructions: &mut Instructions,
+) {
+    // 浮動小数点数の引数はxmm0から8個まで, それ以外はrdiから6個までレジスタで渡す
+    // レジスタに入らない引数は宣言順にスタックで渡す
+    let mut register_args = vec![];
+    let mut stack_args = vec![];
+    let (mut int_register_count, mut float_register_count) = (0, 0);
+    for arg in args {
+        let arg = arg.unwrap();
+        if arg.ty.as_ref().unwrap().is_float() {
+            if float_register_count < FLOAT_ARG_REGISTERS_COUNT {
+                register_args.push((arg, format!("xmm{}", float_register_count)));
+                float_register_count += 1;
+                continue;
+            }
+        } else if int_register_count < ARG_REGISTERS_64.len() {
+            register_args.push((arg, ARG_REGISTERS_64[int_register_count].to_string()));
+            int_register_count += 1;
+            continue;
         }
-        None => {}
+        stack_args.push(arg);
+    }
+    let stack_args_count = stack_args.len();
+    // call時にrspが16の倍数になるよう, 引数を積む前にパディングを入れる
+    // パディング(0か8)の値はスタックに保存しておき, 呼び出し後に戻す
+    // パディング, 保存した値, スタック引数の合計で16の倍数になればよい
+    instructions.push("    mov rax, rsp");
+    instructions.push("    and rax, 15");
+    if stack_args_count.is_multiple_of(2) {
+        instructions.push("    xor rax, 8");
+    }
+    instructions.push("    sub rsp, rax");
+    instructions.push("    push rax");
+    // 引数の中の関数呼び出しがレジスタを壊さないように, 全ての引数を先にスタックに積む
+    // スタックで渡す引数が下に残るように先に積み, それぞれ右から順に評価する
+    for arg in stack_args.into_iter().rev() {
+        compile_node(*arg, instructions);
+    }
+    let registers: Vec<String> = register_args
+        .iter()
+        .map(|(_, register)| register.clone())
+        .collect();
+    for (arg, _) in register_args.into_iter().rev() {
+        compile_node(*arg, instructions);
+    }
+    // 呼び出すアドレスは引数の上に積まれている
+    if let Some(callee_node) = callee_link {
+        compile_node(*callee_node, instructions);
+        instructions.push("    pop r10");
+    }
+    for register in registers {
+        if register.starts_with("xmm") {
+            instructions.push("    pop rax");
+            instructions.push(format!("    movq {}, rax", register));
+        } else {
+            instructions.push(format!("    pop {}", register));
+        }
+    }
+    // 可変長引数の関数のためにalにはベクタレジスタの引数の数を入れておく
+    instructions.push(format!("    mov rax, {}", float_register_count));
+    instructions.push(format!("    call {}", call_operand));
+    if stack_args_count != 0 {
+        instructions.push(format!("    add rsp, {}", stack_args_count * 8));
+    }
+    instructions.push("    pop rdi");
+    instructions.push("    add rsp, rdi");
+    // 戻り値の上位bitは不定なので, 戻り値の型に合わせて拡張する
+    // 浮動小数点数の戻り値はxmm0に入っている
+    if return_ty.is_float() {
+        push_float_result(return_ty, instructions);
+    } else {
+        extend_register(return_ty, "rax", instructions);
+    }
+    instructions.push("    push rax");
+}
+
+fn compile_ast(mut ast: AST, instructions: &mut Instructions) {
+    if let Some(top_node) = ast.root.take() {
+        compile_node(*top_node, instructions);
     }
 }
 
 fn compile_function_prologue(function_info: &FuntionInfo, instructions: &mut Instructions) {
-    instructions.push(format!(""));
+    instructions.push(String::new());
     instructions.push(format!("{}:", function_info.function_name));
-    instructions.push(format!("    push rbp"));
-    instructions.push(format!("    mov rbp, rsp"));
-    // AST側で7個以上の引数は拒否している
-    for arg_index in 0..function_info.args_count {
-        if arg_index == 0 {
-            instructions.push(format!("    mov [rbp - 8], rdi"));
-        } else if arg_index == 1 {
-            instructions.push(format!("    mov [rbp - 16], rsi"));
-        } else if arg_index == 2 {
-            instructions.push(format!("    mov [rbp - 24], rdx"));
-        } else if arg_index == 3 {
-            instructions.push(format!("    mov [rbp - 32], rcx"));
-        } else if arg_index == 4 {
-            instructions.push(format!("    mov [rbp - 40], r8"));
-        } else if arg_index == 5 {
-            instructions.push(format!("    mov [rbp - 48], r9"));
-        }
-    }
-
-    // スタックサイズは引数なので, 引数分引いた分スタックを下げる
-    let local_variable_size = function_info.local_stack_size;
-
-    if local_variable_size % 16 == 0 && local_variable_size != 0 {
+    instructions.push("    push rbp");
+    instructions.push("    mov rbp, rsp");
+    // 関数呼び出し時にrspが16の倍数になるようにスタックを確保する
+    // 引数の書き込み先がrspより下に残らないよう, 引数を保存する前に確保する
+    let local_variable_size = align_to(function_info.local_stack_size, 16);
+    if local_variable_size != 0 {
         instructions.push(format!("    sub rsp, {}", local_variable_size));
-    } else if local_variable_size % 16 != 0 && local_variable_size != 0 {
-        instructions.push(format!("    sub rsp, {}", local_variable_size + 8));
+    }
+    // 呼び出し側と同じ規則で, 引数ごとにレジスタかスタックかを決める
+    let (mut int_register_count, mut float_register_count, mut stack_count) = (0, 0, 0);
+    for arg in &function_info.args {
+        let is_float = arg.ty.is_float();
+        if is_float && float_register_count < FLOAT_ARG_REGISTERS_COUNT {
+            let instruction = if arg.ty == Type::Float {
+                "movss"
+            } else {
+                "movsd"
+            };
+            instructions.push(format!(
+                "    {} [rbp - {}], xmm{}",
+                instruction, arg.offset, float_register_count
+            ));
+            float_register_count += 1;
+            continue;
+        }
+        let arg_index = int_register_count;
+        if is_float || int_register_count >= ARG_REGISTERS_64.len() {
+            // レジスタに入らない引数は呼び出し元がスタックに積んでいる
+            // [rbp]が退避したrbp, [rbp + 8]が戻りアドレスなので, その上から並んでいる
+            let stack_offset = 16 + stack_count * 8;
+            stack_count += 1;
+            instructions.push(format!("    mov rax, [rbp + {}]", stack_offset));
+            if arg.ty.size() == 1 {
+                instructions.push(format!("    mov [rbp - {}], al", arg.offset));
+            } else if arg.ty.size() == 2 {
+                instructions.push(format!("    mov [rbp - {}], ax", arg.offset));
+            } else if arg.ty.size() == 4 {
+                instructions.push(format!("    mov [rbp - {}], eax", arg.offset));
+            } else {
+                instructions.push(format!("    mov [rbp - {}], rax", arg.offset));
+            }
+            continue;
+        }
+        int_register_count += 1;
+        if arg.ty.size() == 1 {
+            instructions.push(format!(
+                "    mov [rbp - {}], {}",
+                arg.offset, ARG_REGISTERS_8[arg_index]
+            ));
+        } else if arg.ty.size() == 2 {
+            instructions.push(format!(
+                "    mov [rbp - {}], {}",
+                arg.offset, ARG_REGISTERS_16[arg_index]
+            ));
+        } else if arg.ty.size() == 4 {
+            instructions.push(format!(
+                "    mov [rbp - {}], {}",
+                arg.offset, ARG_REGISTERS_32[arg_index]
+            ));
+        } else {
+            instructions.push(format!(
+                "    mov [rbp - {}], {}",
+                arg.offset, ARG_REGISTERS_64[arg_index]
+            ));
+        }
     }
 }
 
+// return文がないまま関数末尾に来た場合
+// raxには最後に評価した式文の値が残っている
 fn compile_function_epilogue(instructions: &mut Instructions) {
-    instructions.push(format!("    pop rax"));
-    instructions.push(format!("    mov rsp, rbp"));
-    instructions.push(format!("    pop rbp"));
-    instructions.push(format!("    ret"));
+    instructions.push("    mov rsp, rbp");
+    instructions.push("    pop rbp");
+    instructions.push("    ret");
 }
 
 // function_astからアセンブラを出力する
-pub fn compile_function_ast(function_ast: FunctionAST) -> Vec<String> {
-    let mut instructions = Instructions::new();
-    compile_function_prologue(&function_ast.function_info, &mut instructions);
-    compile_ast(function_ast.function_ast, &mut instructions);
-    compile_function_epilogue(&mut instructions);
-    instructions.vec
+pub fn compile_function_ast(function_ast: FunctionAST, instructions: &mut Instructions) {
+    instructions.function_name = function_ast.function_info.function_name.clone();
+    compile_function_prologue(&function_ast.function_info, instructions);
+    compile_ast(function_ast.function_ast, instructions);
+    compile_function_epilogue(instructions);
+}
+
+// 初期値のあるグローバル変数は.data, ないものは.bssに置く
+pub fn compile_global_variables(program_info: &ProgramInfo, instructions: &mut Instructions) {
+    for variable in &program_info.global_variables {
+        instructions.push(String::new());
+        match variable.initial_value {
+            Some(value) => {
+                instructions.push("    .data");
+                instructions.push(format!("    .align {}", variable.ty.align()));
+                instructions.push(format!("{}:", variable.name));
+                // 初期値はスカラー型の変数にだけ書けるので, 型から値の幅を決めて残りは0で埋める
+                let (directive, value_size) = match variable.ty {
+                    Type::Char | Type::UChar => (".byte", 1),
+                    Type::Short | Type::UShort => (".short", 2),
+                    Type::Int | Type::UInt | Type::Float => (".long", 4),
+                    _ => (".quad", 8),
+                };
+                instructions.push(format!("    {} {}", directive, value));
+                if variable.ty.size() > value_size {
+                    instructions.push(format!("    .zero {}", variable.ty.size() - value_size));
+                }
+            }
+            None => {
+                instructions.push("    .bss");
+                instructions.push(format!("    .align {}", variable.ty.align()));
+                instructions.push(format!("{}:", variable.name));
+                instructions.push(format!("    .zero {}", variable.ty.size()));
+            }
+        }
+    }
+}
+
+// 文字列リテラルを.rodataに出力する
+pub fn compile_string_literals(instructions: &mut Instructions) {
+    if instructions.string_literals.is_empty() {
+        return;
+    }
+    let string_literals = std::mem::take(&mut instructions.string_literals);
+    instructions.push(String::new());
+    instructions.push("    .section .rodata");
+    for (label_count, bytes) in string_literals.iter().enumerate() {
+        instructions.push(format!(".LC{}:", label_count));
+        instructions.push(format!("    .string \"{}\"", escape_string(bytes)));
+    }
+}
+
+// アセンブラの文字列表記に変換する, 表示できない文字は8進数で表す
+fn escape_string(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for byte in bytes {
+        if *byte == b'"' || *byte == b'\\' {
+            escaped.push('\\');
+            escaped.push(*byte as char);
+        } else if byte.is_ascii_graphic() || *byte == b' ' {
+            escaped.push(*byte as char);
+        } else {
+            escaped.push_str(&format!("\\{:03o}", byte));
+        }
+    }
+    escaped
 }
@@ -1,4 +1,4 @@
-use crate::tokenizer::{TokenList, PROGRAM_TEXT};
+use crate::tokenizer::{SourcePos, TokenList, PROGRAM_TEXT};
 use std::process::exit;
 
 // エラー行に全角表示文字があるとその文ずれる
@@ -8,7 +8,7 @@ pub fn invalid_token_exit(error_text: &str, token_list: &TokenList) -> ! {
     if let Some(token) = token_list.peek_head() {
         error_exit(error_text, token.token_pos);
     } else {
-        let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
+        let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
         error_exit(error_text, tail_pos);
     }
 }
@@ -17,9 +17,22 @@ pub fn invalid_token_exit(error_text: &str, token_list: &TokenList) -> ! {
 // (スペースは半角のため)
 // utfコードから全角判断してスペースを余分に追加すれば対応できるはず
 pub fn error_exit(error_text: &str, error_pos: usize) -> ! {
-    let (error_line_string, error_line, error_column) =
-        PROGRAM_TEXT.get().unwrap().get_error_line(error_pos);
-    let line_string = format!("line{}: ", error_line);
+    // プリプロセス後の位置を元のファイルでの位置に戻す
+    let origin = PROGRAM_TEXT.read().unwrap().get_origin(error_pos);
+    source_error_exit(error_text, origin);
+}
+
+// 元のファイルでの位置にエラーを立てる
+// トークン化の前に処理するプリプロセッサはこちらを使う
+pub fn source_error_exit(error_text: &str, origin: SourcePos) -> ! {
+    let (file_name, error_line_string, error_line, error_column) =
+        PROGRAM_TEXT.read().unwrap().get_error_line(origin);
+    // コマンドラインで渡したプログラム以外はファイル名も表示する
+    let line_string = if file_name.is_empty() {
+        format!("line{}: ", error_line)
+    } else {
+        format!("{}:line{}: ", file_name, error_line)
+    };
     let error_space = " ".repeat(line_string.len() + error_column);
     println!("{}{}", line_string, error_line_string);
     let error_string = format!("{}^{}", error_space, error_text);
@@ -1,12 +1,16 @@
 use std::env;
 use std::fs;
 use std::io::{BufWriter, Write};
+use std::path::PathBuf;
 
 mod ast;
 mod compiler;
 mod error;
+mod preprocessor;
+#[cfg(test)]
 mod tests;
 mod tokenizer;
+mod types;
 
 fn write_header<T: Write>(buf: &mut T) {
     writeln!(buf, ".intel_syntax noprefix").unwrap();
@@ -17,25 +21,78 @@ fn write_operation<T: Write>(buf: &mut T, instruction: String) {
     writeln!(buf, "{}", instruction).unwrap();
 }
 
-pub fn output_asembly(input_text: &str) {
+// プログラムをアセンブラの命令列にコンパイルする, エラーの場合はプロセスを終了する
+pub fn compile_program(
+    input_text: &str,
+    options: &preprocessor::PreprocessorOptions,
+) -> Vec<String> {
+    preprocessor::preprocess(input_text, options);
+    let mut token_list = tokenizer::text_tokenizer();
+    let program_ast = ast::ProgramAST::make_program_ast(&mut token_list);
+    let mut instructions = compiler::Instructions::new();
+    for function_ast in program_ast.function_asts {
+        compiler::compile_function_ast(function_ast, &mut instructions);
+    }
+    compiler::compile_global_variables(&program_ast.program_info, &mut instructions);
+    compiler::compile_string_literals(&mut instructions);
+    instructions.vec
+}
+
+pub fn output_asembly(input_text: &str, options: &preprocessor::PreprocessorOptions) {
+    let instructions = compile_program(input_text, options);
     let mut file = BufWriter::new(fs::File::create("tmp.s").unwrap());
     write_header(&mut file);
+    instructions
+        .into_iter()
+        .for_each(|instruction| write_operation(&mut file, instruction));
+}
 
-    let token_list_vec = tokenizer::text_tokenizer(input_text);
-    for mut token_list in token_list_vec {
-        let function_ast = ast::FunctionAST::make_function_ast(&mut token_list);
-        let instruction_vec = compiler::compile_function_ast(function_ast);
-        instruction_vec
-            .into_iter()
-            .for_each(|instruction| write_operation(&mut file, instruction));
+// -D NAME=value は #define NAME value, -D NAME は #define NAME 1 とする
+fn define_directive(definition: &str) -> String {
+    match definition.find('=') {
+        Some(eq_pos) => format!(
+            "#define {} {}",
+            &definition[..eq_pos],
+            &definition[eq_pos + 1..]
+        ),
+        None => format!("#define {} 1", definition),
     }
 }
 
+// toy_compiler [-I dir] [-D NAME[=value]] [-U NAME]... program
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        return;
+    let mut options = preprocessor::PreprocessorOptions::new();
+    let mut input_text = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        // -I dir と -Idir のどちらの書き方も受け付ける, -D, -Uも同様
+        let (option, value) = if arg == "-I" || arg == "-D" || arg == "-U" {
+            match args.next() {
+                Some(value) => (arg[..2].to_string(), value),
+                None => return,
+            }
+        } else if arg.starts_with("-I") || arg.starts_with("-D") || arg.starts_with("-U") {
+            (arg[..2].to_string(), arg[2..].to_string())
+        } else {
+            (String::new(), arg)
+        };
+        if option == "-I" {
+            options.include_paths.push(PathBuf::from(value));
+        } else if option == "-D" {
+            options
+                .command_line_directives
+                .push(define_directive(&value));
+        } else if option == "-U" {
+            options
+                .command_line_directives
+                .push(format!("#undef {}", value));
+        } else if input_text.is_none() {
+            input_text = Some(value);
+        } else {
+            return;
+        }
+    }
+    if let Some(input_text) = input_text {
+        output_asembly(&input_text, &options);
     }
-    let input_text = &args[1];
-    output_asembly(input_text);
 }
@@ -0,0 +1,1106 @@
+use crate::error::source_error_exit;
+use crate::tokenizer::{ProgramText, SourcePos, PROGRAM_TEXT};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// #includeの入れ子の上限, 自身をincludeし続ける場合に止める
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+// 展開後の1文字と, その文字が元々あったファイルでの位置
+type SourceChar = (char, SourcePos);
+
+// コマンドラインで指定するプリプロセッサの設定
+pub struct PreprocessorOptions {
+    // #include <file> を探すディレクトリ
+    // #include "file" はインクルード元と同じディレクトリを探した後にここを探す
+    pub include_paths: Vec<PathBuf>,
+    // -D, -Uを指定した順に#define, #undefの行にしたもの
+    // プログラムの前に処理する
+    pub command_line_directives: Vec<String>,
+}
+
+impl PreprocessorOptions {
+    pub fn new() -> Self {
+        PreprocessorOptions {
+            include_paths: vec![],
+            command_line_directives: vec![],
+        }
+    }
+}
+
+// マクロの置き換え後の内容を#defineの時点で分解したもの
+enum MacroPiece {
+    // そのまま出力する文字列
+    Text(Vec<char>),
+    // 展開した実引数に置き換える仮引数
+    Param(usize),
+    // #param, 実引数を文字列リテラルにする
+    Stringify(usize),
+    // ##, 前後を空白を挟まずに連結する
+    Paste,
+}
+
+// #defineで定義したマクロ
+struct Macro {
+    name: String,
+    // 関数形式マクロの仮引数, オブジェクト形式マクロはNone
+    params: Option<Vec<String>>,
+    pieces: Vec<MacroPiece>,
+}
+
+// #if, #ifdef, #ifndefから#endifまでの状態
+struct Conditional {
+    // 現在のグループの行を出力するか
+    is_active: bool,
+    // 条件が成立したグループが既にあるか
+    is_taken: bool,
+    // 外側のグループが出力されるか
+    is_parent_active: bool,
+    has_else: bool,
+    origin: SourcePos,
+}
+
+struct Preprocessor<'a> {
+    options: &'a PreprocessorOptions,
+    macros: Vec<Macro>,
+    conditionals: Vec<Conditional>,
+    include_depth: usize,
+}
+
+// 入力テキストをプリプロセスし, 結果をPROGRAM_TEXTに設定する
+// 展開後の各文字は元のファイルでの位置を持つので, エラーは元のファイルと行を指す
+pub fn preprocess(input_text: &str, options: &PreprocessorOptions) {
+    *PROGRAM_TEXT.write().unwrap() = ProgramText::new();
+    let mut preprocessor = Preprocessor {
+        options,
+        macros: vec![],
+        conditionals: vec![],
+        include_depth: 0,
+    };
+    if !options.command_line_directives.is_empty() {
+        // -D, -Uはディレクティブとして処理し, 出力は捨てる
+        let directives = options.command_line_directives.join("\n");
+        preprocessor.preprocess_file(
+            "<command line>".to_string(),
+            directives.chars().collect(),
+            Path::new(""),
+        );
+    }
+    // コマンドラインで渡したプログラムからの#include "file"はカレントディレクトリから探す
+    let text =
+        preprocessor.preprocess_file(String::new(), input_text.chars().collect(), Path::new(""));
+    PROGRAM_TEXT.write().unwrap().set_text(text);
+}
+
+impl<'a> Preprocessor<'a> {
+    fn find_macro(&self, name: &str) -> Option<&Macro> {
+        self.macros.iter().find(|defined| defined.name == name)
+    }
+
+    fn is_active(&self) -> bool {
+        self.conditionals
+            .last()
+            .is_none_or(|conditional| conditional.is_active)
+    }
+
+    // ファイルを行単位で処理する
+    // #で始まる行はディレクティブ, それ以外の行はまとめてマクロを展開する
+    fn preprocess_file(&mut self, name: String, text: Vec<char>, dir: &Path) -> Vec<SourceChar> {
+        let file_index = PROGRAM_TEXT.write().unwrap().add_source_file(name, text);
+        let source: Vec<SourceChar> = PROGRAM_TEXT
+            .read()
+            .unwrap()
+            .get_source_text(file_index)
+            .iter()
+            .enumerate()
+            .map(|(pos, ch)| (*ch, SourcePos { file_index, pos }))
+            .collect();
+        let source = remove_comments(source);
+        // 対応する#endifはincludeしたファイルの中になければならない
+        let conditional_base = self.conditionals.len();
+
+        let mut output = vec![];
+        let mut text_lines = vec![];
+        for line in source.split_inclusive(|(ch, _)| *ch == '\n') {
+            let mut index = 0;
+            skip_spaces(line, &mut index);
+            if line.get(index).map(|(ch, _)| *ch) != Some('#') {
+                if self.is_active() {
+                    text_lines.extend_from_slice(line);
+                } else if line.last().map(|(ch, _)| *ch) == Some('\n') {
+                    // 出力しない行も行の区切りは残す
+                    text_lines.push(line[line.len() - 1]);
+                }
+                continue;
+            }
+            output.extend(self.expand_text(&text_lines, &[]));
+            text_lines.clear();
+            // 行末の改行はディレクティブに含めず, 行の区切りとして出力に残す
+            let (directive, newline) = match line.split_last() {
+                Some((last, directive)) if last.0 == '\n' => (directive, Some(*last)),
+                _ => (line, None),
+            };
+            self.run_directive(
+                &directive[index + 1..],
+                line[index].1,
+                dir,
+                conditional_base,
+                &mut output,
+            );
+            output.extend(newline);
+        }
+        if self.conditionals.len() > conditional_base {
+            source_error_exit(
+                "unterminated conditional directive",
+                self.conditionals[conditional_base].origin,
+            );
+        }
+        output.extend(self.expand_text(&text_lines, &[]));
+        output
+    }
+
+    // #に続くディレクティブを処理する
+    fn run_directive(
+        &mut self,
+        directive: &[SourceChar],
+        hash_origin: SourcePos,
+        dir: &Path,
+        conditional_base: usize,
+        output: &mut Vec<SourceChar>,
+    ) {
+        let mut index = 0;
+        skip_spaces(directive, &mut index);
+        // #だけの行は何もしない
+        if index == directive.len() {
+            return;
+        }
+        let name_origin = directive[index].1;
+        let name = match pop_identifier(directive, &mut index) {
+            Some(name) => name,
+            None if self.is_active() => {
+                source_error_exit("invalid preprocessing directive", name_origin)
+            }
+            None => return,
+        };
+        let rest = &directive[index..];
+        let is_conditional_directive =
+            ["if", "ifdef", "ifndef", "elif", "else", "endif"].contains(&name.as_str());
+        if is_conditional_directive {
+            self.run_conditional_directive(&name, rest, hash_origin, conditional_base);
+        } else if !self.is_active() {
+            // 出力しないグループの中は条件ディレクティブ以外を無視する
+        } else if name == "include" {
+            self.include(rest, hash_origin, dir, output);
+        } else if name == "define" {
+            self.define(rest, hash_origin);
+        } else if name == "undef" {
+            let name = pop_macro_name(rest, &mut 0, hash_origin);
+            self.macros.retain(|defined| defined.name != name);
+        } else if name == "error" {
+            let message: String = rest.iter().map(|(ch, _)| *ch).collect();
+            source_error_exit(&format!("#error {}", message.trim()), hash_origin);
+        } else {
+            source_error_exit(
+                &format!("invalid preprocessing directive #{}", name),
+                name_origin,
+            );
+        }
+    }
+
+    // #include "file" または #include <file>
+    fn include(
+        &mut self,
+        rest: &[SourceChar],
+        hash_origin: SourcePos,
+        dir: &Path,
+        output: &mut Vec<SourceChar>,
+    ) {
+        let mut index = 0;
+        skip_spaces(rest, &mut index);
+        let (file_origin, closing_ch) = match rest.get(index) {
+            Some(('"', origin)) => (*origin, '"'),
+            Some(('<', origin)) => (*origin, '>'),
+            _ => source_error_exit("#include expects \"FILENAME\" or <FILENAME>", hash_origin),
+        };
+        index += 1;
+        let mut file_name = String::new();
+        loop {
+            match rest.get(index) {
+                Some((ch, _)) if *ch == closing_ch => break,
+                Some((ch, _)) => file_name.push(*ch),
+                None => source_error_exit("#include file name is not closed", file_origin),
+            }
+            index += 1;
+        }
+        index += 1;
+        skip_spaces(rest, &mut index);
+        if index != rest.len() {
+            source_error_exit("extra tokens at end of #include directive", rest[index].1);
+        }
+
+        let path = match self.find_include_file(&file_name, closing_ch == '"', dir) {
+            Some(path) => path,
+            None => source_error_exit(
+                &format!("cannot find include file {}", file_name),
+                file_origin,
+            ),
+        };
+        if self.include_depth == MAX_INCLUDE_DEPTH {
+            source_error_exit("#include nested too deeply", hash_origin);
+        }
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => source_error_exit(
+                &format!("cannot read include file {}", file_name),
+                file_origin,
+            ),
+        };
+        let include_dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        self.include_depth += 1;
+        let included_text = self.preprocess_file(
+            path.display().to_string(),
+            text.chars().collect(),
+            &include_dir,
+        );
+        self.include_depth -= 1;
+        output.extend(included_text);
+    }
+
+    fn find_include_file(&self, file_name: &str, is_quoted: bool, dir: &Path) -> Option<PathBuf> {
+        let mut candidates = vec![];
+        if is_quoted {
+            candidates.push(dir.join(file_name));
+        }
+        for include_path in &self.options.include_paths {
+            candidates.push(include_path.join(file_name));
+        }
+        candidates.into_iter().find(|path| path.is_file())
+    }
+
+    // #define NAME value または #define NAME(params) value
+    fn define(&mut self, rest: &[SourceChar], hash_origin: SourcePos) {
+        let mut index = 0;
+        let name = pop_macro_name(rest, &mut index, hash_origin);
+        // 名前の直後の(は関数形式マクロの仮引数の始まり
+        let params = match rest.get(index) {
+            Some(('(', _)) => {
+                index += 1;
+                Some(pop_macro_params(rest, &mut index, hash_origin))
+            }
+            _ => None,
+        };
+        let pieces = parse_macro_body(&rest[index..], params.as_deref());
+        // 同じ名前のマクロは後の定義で置き換える
+        self.macros.retain(|defined| defined.name != name);
+        self.macros.push(Macro {
+            name,
+            params,
+            pieces,
+        });
+    }
+
+    // #if, #ifdef, #ifndef, #elif, #else, #endif
+    fn run_conditional_directive(
+        &mut self,
+        name: &str,
+        rest: &[SourceChar],
+        hash_origin: SourcePos,
+        conditional_base: usize,
+    ) {
+        if name == "if" || name == "ifdef" || name == "ifndef" {
+            let is_parent_active = self.is_active();
+            // 出力しないグループの中の条件は評価しない
+            let is_active = is_parent_active
+                && match name {
+                    "if" => self.eval_condition(rest, hash_origin),
+                    "ifdef" => {
+                        let name = pop_macro_name(rest, &mut 0, hash_origin);
+                        self.find_macro(&name).is_some()
+                    }
+                    _ => {
+                        let name = pop_macro_name(rest, &mut 0, hash_origin);
+                        self.find_macro(&name).is_none()
+                    }
+                };
+            self.conditionals.push(Conditional {
+                is_active,
+                is_taken: is_active,
+                is_parent_active,
+                has_else: false,
+                origin: hash_origin,
+            });
+            return;
+        }
+        if self.conditionals.len() == conditional_base {
+            source_error_exit(&format!("#{} without #if", name), hash_origin);
+        }
+        if name == "endif" {
+            self.conditionals.pop();
+            return;
+        }
+        let (is_parent_active, is_taken, has_else) = match self.conditionals.last() {
+            Some(conditional) => (
+                conditional.is_parent_active,
+                conditional.is_taken,
+                conditional.has_else,
+            ),
+            None => unreachable!(),
+        };
+        if has_else {
+            source_error_exit(&format!("#{} after #else", name), hash_origin);
+        }
+        let is_active = is_parent_active
+            && !is_taken
+            && (name == "else" || self.eval_condition(rest, hash_origin));
+        if let Some(conditional) = self.conditionals.last_mut() {
+            conditional.is_active = is_active;
+            conditional.is_taken |= is_active;
+            conditional.has_else = name == "else";
+        }
+    }
+
+    // #if, #elifの条件を評価する
+    // defined NAME, defined(NAME)を置き換えてからマクロを展開し, 残った識別子は0とする
+    fn eval_condition(&self, rest: &[SourceChar], hash_origin: SourcePos) -> bool {
+        let mut replaced = vec![];
+        let mut index = 0;
+        while index < rest.len() {
+            let (ch, origin) = rest[index];
+            if ch == '"' || ch == '\'' {
+                let literal_end = skip_literal(rest, index);
+                replaced.extend_from_slice(&rest[index..literal_end]);
+                index = literal_end;
+            } else if ch.is_ascii_digit() {
+                let number_end = skip_while(rest, index, is_pp_number_char);
+                replaced.extend_from_slice(&rest[index..number_end]);
+                index = number_end;
+            } else if ch.is_ascii_alphabetic() || ch == '_' {
+                let identifier_start = index;
+                if pop_identifier(rest, &mut index).as_deref() != Some("defined") {
+                    replaced.extend_from_slice(&rest[identifier_start..index]);
+                    continue;
+                }
+                skip_spaces(rest, &mut index);
+                let has_paren = rest.get(index).map(|(ch, _)| *ch) == Some('(');
+                if has_paren {
+                    index += 1;
+                }
+                let name = pop_macro_name(rest, &mut index, origin);
+                if has_paren {
+                    skip_spaces(rest, &mut index);
+                    if rest.get(index).map(|(ch, _)| *ch) != Some(')') {
+                        source_error_exit("missing ')' after defined", origin);
+                    }
+                    index += 1;
+                }
+                let value = if self.find_macro(&name).is_some() {
+                    '1'
+                } else {
+                    '0'
+                };
+                replaced.push((value, origin));
+            } else {
+                replaced.push(rest[index]);
+                index += 1;
+            }
+        }
+        let tokens = tokenize_condition(&self.expand_text(&replaced, &[]));
+        if tokens.is_empty() {
+            source_error_exit("#if with no expression", hash_origin);
+        }
+        let mut parser = ConditionParser {
+            tokens,
+            index: 0,
+            unevaluated_depth: 0,
+            end_origin: hash_origin,
+        };
+        let value = parser.conditional();
+        if let Some((_, origin)) = parser.tokens.get(parser.index) {
+            source_error_exit("extra tokens in #if expression", *origin);
+        }
+        value != 0
+    }
+
+    // テキスト中のマクロを展開する
+    // 展開した文字はマクロを使った位置を元の位置とし, 展開中のマクロは再度展開しない
+    fn expand_text(&self, input: &[SourceChar], disabled: &[String]) -> Vec<SourceChar> {
+        let mut output = vec![];
+        let mut index = 0;
+        while index < input.len() {
+            let (ch, origin) = input[index];
+            if ch == '"' || ch == '\'' {
+                // 文字列, 文字リテラルの中は置き換えない
+                let literal_end = skip_literal(input, index);
+                output.extend_from_slice(&input[index..literal_end]);
+                index = literal_end;
+            } else if ch.is_ascii_digit() {
+                // 1ULのような数値の接尾辞を識別子として扱わない
+                let number_end = skip_while(input, index, is_pp_number_char);
+                output.extend_from_slice(&input[index..number_end]);
+                index = number_end;
+            } else if ch.is_ascii_alphabetic() || ch == '_' {
+                let identifier_end = skip_while(input, index, is_identifier_char);
+                let name: String = input[index..identifier_end]
+                    .iter()
+                    .map(|(ch, _)| *ch)
+                    .collect();
+                index = identifier_end;
+                let defined = match self.find_macro(&name) {
+                    Some(defined) if !disabled.contains(&name) => defined,
+                    _ => {
+                        output.extend(name.chars().map(|ch| (ch, origin)));
+                        continue;
+                    }
+                };
+                let args = match &defined.params {
+                    Some(params) => {
+                        // 名前の後に(が続かなければ関数形式マクロとして展開しない
+                        let paren_index = skip_while(input, index, char::is_whitespace);
+                        if input.get(paren_index).map(|(ch, _)| *ch) != Some('(') {
+                            output.extend(name.chars().map(|ch| (ch, origin)));
+                            continue;
+                        }
+                        let (mut args, args_end) =
+                            pop_macro_args(input, paren_index, &name, origin);
+                        index = args_end;
+                        // F()は仮引数のないマクロの呼び出しとする
+                        if params.is_empty() && args.len() == 1 && args[0].is_empty() {
+                            args.clear();
+                        }
+                        if args.len() != params.len() {
+                            source_error_exit(
+                                &format!(
+                                    "macro {} requires {} arguments, but {} given",
+                                    name,
+                                    params.len(),
+                                    args.len()
+                                ),
+                                origin,
+                            );
+                        }
+                        args
+                    }
+                    None => vec![],
+                };
+                let replaced = self.substitute(defined, &args, origin, disabled);
+                let mut disabled = disabled.to_vec();
+                disabled.push(name);
+                // 前後のトークンと繋がらないように空白で区切る
+                output.push((' ', origin));
+                output.extend(self.expand_text(&replaced, &disabled));
+                output.push((' ', origin));
+            } else {
+                output.push(input[index]);
+                index += 1;
+            }
+        }
+        output
+    }
+
+    // マクロの仮引数を実引数で置き換える
+    // 実引数は先に展開するが, #と##の対象はそのまま使う
+    fn substitute(
+        &self,
+        defined: &Macro,
+        args: &[Vec<SourceChar>],
+        origin: SourcePos,
+        disabled: &[String],
+    ) -> Vec<SourceChar> {
+        let mut output = vec![];
+        for (piece_index, piece) in defined.pieces.iter().enumerate() {
+            let is_pasted = matches!(defined.pieces.get(piece_index + 1), Some(MacroPiece::Paste))
+                || (piece_index > 0
+                    && matches!(defined.pieces[piece_index - 1], MacroPiece::Paste));
+            match piece {
+                MacroPiece::Text(text) => output.extend(text.iter().map(|ch| (*ch, origin))),
+                MacroPiece::Param(param_index) if is_pasted => {
+                    output.extend_from_slice(&args[*param_index])
+                }
+                MacroPiece::Param(param_index) => {
+                    output.push((' ', origin));
+                    output.extend(self.expand_text(&args[*param_index], disabled));
+                    output.push((' ', origin));
+                }
+                MacroPiece::Stringify(param_index) => output.extend(
+                    stringify(&args[*param_index])
+                        .chars()
+                        .map(|ch| (ch, origin)),
+                ),
+                // ##の前後の空白は#defineの時点で除いてあるので, 並べるだけで連結される
+                MacroPiece::Paste => {}
+            }
+        }
+        output
+    }
+}
+
+fn is_identifier_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+fn is_pp_number_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'
+}
+
+// startから条件を満たす文字が続く終わりの位置を返す
+fn skip_while(input: &[SourceChar], start: usize, condition: fn(char) -> bool) -> usize {
+    let mut index = start;
+    while index < input.len() && condition(input[index].0) {
+        index += 1;
+    }
+    index
+}
+
+fn skip_spaces(input: &[SourceChar], index: &mut usize) {
+    *index = skip_while(input, *index, |ch| ch == ' ' || ch == '\t' || ch == '\r');
+}
+
+fn pop_identifier(input: &[SourceChar], index: &mut usize) -> Option<String> {
+    match input.get(*index) {
+        Some((ch, _)) if ch.is_ascii_alphabetic() || *ch == '_' => {}
+        _ => return None,
+    }
+    let identifier_end = skip_while(input, *index, is_identifier_char);
+    let identifier = input[*index..identifier_end]
+        .iter()
+        .map(|(ch, _)| *ch)
+        .collect();
+    *index = identifier_end;
+    Some(identifier)
+}
+
+// マクロ名を読む, 前の空白は読み飛ばす
+fn pop_macro_name(input: &[SourceChar], index: &mut usize, hash_origin: SourcePos) -> String {
+    skip_spaces(input, index);
+    let name_origin = input.get(*index).map_or(hash_origin, |(_, origin)| *origin);
+    match pop_identifier(input, index) {
+        Some(name) => name,
+        None => source_error_exit("macro name must be an identifier", name_origin),
+    }
+}
+
+// #define NAME(の後から)までの仮引数を読む
+fn pop_macro_params(
+    input: &[SourceChar],
+    index: &mut usize,
+    hash_origin: SourcePos,
+) -> Vec<String> {
+    let mut params = vec![];
+    skip_spaces(input, index);
+    if input.get(*index).map(|(ch, _)| *ch) == Some(')') {
+        *index += 1;
+        return params;
+    }
+    loop {
+        skip_spaces(input, index);
+        let param_origin = input.get(*index).map_or(hash_origin, |(_, origin)| *origin);
+        let param = match pop_identifier(input, index) {
+            Some(param) => param,
+            None => source_error_exit("expected parameter name", param_origin),
+        };
+        if params.contains(&param) {
+            source_error_exit(
+                &format!("duplicate macro parameter {}", param),
+                param_origin,
+            );
+        }
+        params.push(param);
+        skip_spaces(input, index);
+        match input.get(*index) {
+            Some((',', _)) => *index += 1,
+            Some((')', _)) => {
+                *index += 1;
+                return params;
+            }
+            Some((_, origin)) => {
+                source_error_exit("expected ',' or ')' in macro parameter list", *origin)
+            }
+            None => source_error_exit("missing ')' in macro parameter list", hash_origin),
+        }
+    }
+}
+
+// マクロの置き換え後の内容を仮引数, #, ##で区切る
+fn parse_macro_body(body: &[SourceChar], params: Option<&[String]>) -> Vec<MacroPiece> {
+    let body = trim_spaces(body);
+    let mut pieces = vec![];
+    let mut text = vec![];
+    let mut index = 0;
+    while index < body.len() {
+        let (ch, origin) = body[index];
+        let next_ch = body.get(index + 1).map(|(ch, _)| *ch);
+        if ch == '"' || ch == '\'' {
+            let literal_end = skip_literal(body, index);
+            text.extend(body[index..literal_end].iter().map(|(ch, _)| *ch));
+            index = literal_end;
+        } else if ch == '#' && next_ch == Some('#') {
+            // 前後の空白を除いて連結する
+            while text.last().is_some_and(|ch: &char| ch.is_whitespace()) {
+                text.pop();
+            }
+            push_text_piece(&mut pieces, &mut text);
+            index += 2;
+            skip_spaces(body, &mut index);
+            if pieces.is_empty() || index == body.len() {
+                source_error_exit(
+                    "'##' cannot appear at either end of a macro expansion",
+                    origin,
+                );
+            }
+            pieces.push(MacroPiece::Paste);
+        } else if ch == '#' && params.is_some() {
+            index += 1;
+            skip_spaces(body, &mut index);
+            let param_index = pop_identifier(body, &mut index).and_then(|name| {
+                params.and_then(|params| params.iter().position(|param| *param == name))
+            });
+            match param_index {
+                Some(param_index) => {
+                    push_text_piece(&mut pieces, &mut text);
+                    pieces.push(MacroPiece::Stringify(param_index));
+                }
+                None => source_error_exit("'#' is not followed by a macro parameter", origin),
+            }
+        } else if ch.is_ascii_digit() {
+            let number_end = skip_while(body, index, is_pp_number_char);
+            text.extend(body[index..number_end].iter().map(|(ch, _)| *ch));
+            index = number_end;
+        } else if ch.is_ascii_alphabetic() || ch == '_' {
+            let identifier_start = index;
+            let name = pop_identifier(body, &mut index).unwrap();
+            match params.and_then(|params| params.iter().position(|param| *param == name)) {
+                Some(param_index) => {
+                    push_text_piece(&mut pieces, &mut text);
+                    pieces.push(MacroPiece::Param(param_index));
+                }
+                None => text.extend(body[identifier_start..index].iter().map(|(ch, _)| *ch)),
+            }
+        } else {
+            text.push(ch);
+            index += 1;
+        }
+    }
+    push_text_piece(&mut pieces, &mut text);
+    pieces
+}
+
+fn push_text_piece(pieces: &mut Vec<MacroPiece>, text: &mut Vec<char>) {
+    if !text.is_empty() {
+        pieces.push(MacroPiece::Text(std::mem::take(text)));
+    }
+}
+
+// 関数形式マクロの呼び出しの(から)までの実引数を読み, 実引数と)の次の位置を返す
+// 括弧の中のカンマでは区切らず, 改行は空白として扱う
+fn pop_macro_args(
+    input: &[SourceChar],
+    paren_index: usize,
+    name: &str,
+    origin: SourcePos,
+) -> (Vec<Vec<SourceChar>>, usize) {
+    let mut args = vec![];
+    let mut arg = vec![];
+    let mut depth = 0;
+    let mut index = paren_index + 1;
+    loop {
+        let (ch, ch_origin) = match input.get(index) {
+            Some(source_char) => *source_char,
+            None => source_error_exit(
+                &format!("unterminated argument list invoking macro {}", name),
+                origin,
+            ),
+        };
+        match ch {
+            '"' | '\'' => {
+                let literal_end = skip_literal(input, index);
+                arg.extend_from_slice(&input[index..literal_end]);
+                index = literal_end;
+                continue;
+            }
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                args.push(trim_spaces(&arg).to_vec());
+                return (args, index + 1);
+            }
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(trim_spaces(&arg).to_vec());
+                arg.clear();
+                index += 1;
+                continue;
+            }
+            _ => {}
+        }
+        if ch == '\n' {
+            arg.push((' ', ch_origin));
+        } else {
+            arg.push((ch, ch_origin));
+        }
+        index += 1;
+    }
+}
+
+fn trim_spaces(input: &[SourceChar]) -> &[SourceChar] {
+    let start = skip_while(input, 0, char::is_whitespace);
+    let mut end = input.len();
+    while end > start && input[end - 1].0.is_whitespace() {
+        end -= 1;
+    }
+    &input[start..end]
+}
+
+// #paramの実引数を文字列リテラルにする
+// トークン間の空白の並びは1つの空白にする
+// 文字列, 文字リテラルの中は空白をそのまま残し, "と\\をエスケープする
+fn stringify(arg: &[SourceChar]) -> String {
+    let mut literal = "\"".to_string();
+    let mut has_space = false;
+    let mut index = 0;
+    while index < arg.len() {
+        let ch = arg[index].0;
+        if ch.is_whitespace() {
+            has_space = true;
+            index += 1;
+            continue;
+        }
+        if has_space {
+            literal.push(' ');
+            has_space = false;
+        }
+        if ch == '"' || ch == '\'' {
+            let end = skip_literal(arg, index);
+            for (literal_ch, _) in &arg[index..end] {
+                if *literal_ch == '"' || *literal_ch == '\\' {
+                    literal.push('\\');
+                }
+                literal.push(*literal_ch);
+            }
+            index = end;
+            continue;
+        }
+        literal.push(ch);
+        index += 1;
+    }
+    literal.push('"');
+    literal
+}
+
+// startの"か'で始まるリテラルの終わりの位置を返す
+// 閉じられていない場合は行末までとし, エラーはトークン化の際に立てる
+fn skip_literal(input: &[SourceChar], start: usize) -> usize {
+    let quote = input[start].0;
+    let mut index = start + 1;
+    while index < input.len() {
+        match input[index].0 {
+            '\\' => index += 2,
+            '\n' => return index,
+            ch if ch == quote => return index + 1,
+            _ => index += 1,
+        }
+    }
+    input.len()
+}
+
+// 行末の\による行の連結とコメントの除去を行う
+// コメントは1つの空白に置き換える
+fn remove_comments(source: Vec<SourceChar>) -> Vec<SourceChar> {
+    let mut output = vec![];
+    let mut index = 0;
+    while index < source.len() {
+        let (ch, origin) = source[index];
+        let next_ch = source.get(index + 1).map(|(ch, _)| *ch);
+        if ch == '\\' && next_ch == Some('\n') {
+            index += 2;
+        } else if ch == '"' || ch == '\'' {
+            let literal_end = skip_literal(&source, index);
+            output.extend_from_slice(&source[index..literal_end]);
+            index = literal_end;
+        } else if ch == '/' && next_ch == Some('/') {
+            while index < source.len() && source[index].0 != '\n' {
+                index += 1;
+            }
+            output.push((' ', origin));
+        } else if ch == '/' && next_ch == Some('*') {
+            index += 2;
+            loop {
+                if index + 1 >= source.len() {
+                    source_error_exit("comment is not closed", origin);
+                }
+                if source[index].0 == '*' && source[index + 1].0 == '/' {
+                    index += 2;
+                    break;
+                }
+                index += 1;
+            }
+            output.push((' ', origin));
+        } else {
+            output.push(source[index]);
+            index += 1;
+        }
+    }
+    output
+}
+
+// #ifの条件式のトークン
+enum ConditionToken {
+    Number(i64),
+    Punct(&'static str),
+}
+
+// 2文字の演算子を先に調べる
+const CONDITION_PUNCTUATORS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "+", "-", "*", "/", "%", "<", ">", "!", "~",
+    "&", "|", "^", "?", ":", "(", ")",
+];
+
+// 優先順位の低い順に並べた二項演算子
+const CONDITION_BINARY_OPERATORS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+fn tokenize_condition(input: &[SourceChar]) -> Vec<(ConditionToken, SourcePos)> {
+    let mut tokens = vec![];
+    let mut index = 0;
+    while index < input.len() {
+        let (ch, origin) = input[index];
+        if ch.is_whitespace() {
+            index += 1;
+        } else if ch.is_ascii_digit() {
+            let number_end = skip_while(input, index, is_pp_number_char);
+            let number: String = input[index..number_end].iter().map(|(ch, _)| *ch).collect();
+            let value = parse_condition_number(&number, origin);
+            tokens.push((ConditionToken::Number(value), origin));
+            index = number_end;
+        } else if ch.is_ascii_alphabetic() || ch == '_' {
+            // マクロでない識別子は0とする
+            index = skip_while(input, index, is_identifier_char);
+            tokens.push((ConditionToken::Number(0), origin));
+        } else if ch == '\'' {
+            let literal_end = skip_literal(input, index);
+            let chars: Vec<char> = input[index + 1..literal_end]
+                .iter()
+                .map(|(ch, _)| *ch)
+                .collect();
+            let value = match chars.split_last() {
+                Some(('\'', chars)) => parse_condition_char(chars, origin),
+                _ => source_error_exit("character constant is not closed", origin),
+            };
+            tokens.push((ConditionToken::Number(value), origin));
+            index = literal_end;
+        } else {
+            let head: String = input[index..].iter().take(2).map(|(ch, _)| *ch).collect();
+            match CONDITION_PUNCTUATORS
+                .iter()
+                .find(|punct| head.starts_with(**punct))
+            {
+                Some(punct) => {
+                    tokens.push((ConditionToken::Punct(punct), origin));
+                    index += punct.len();
+                }
+                None => source_error_exit("invalid token in #if expression", origin),
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_condition_number(number: &str, origin: SourcePos) -> i64 {
+    // 整数の接尾辞は値に影響しない
+    let digits = number
+        .trim_end_matches(['u', 'U', 'l', 'L'])
+        .to_ascii_lowercase();
+    let (radix, digits) = if let Some(digits) = digits.strip_prefix("0x") {
+        (16, digits)
+    } else if let Some(digits) = digits.strip_prefix("0b") {
+        (2, digits)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (8, &digits[1..])
+    } else {
+        (10, digits.as_str())
+    };
+    match u64::from_str_radix(digits, radix) {
+        Ok(value) => value as i64,
+        Err(_) => source_error_exit("invalid integer constant in #if expression", origin),
+    }
+}
+
+// 引用符の中の文字を値にする
+fn parse_condition_char(chars: &[char], origin: SourcePos) -> i64 {
+    let value = match chars {
+        [ch] if *ch != '\\' => *ch,
+        ['\\', 'n'] => '\n',
+        ['\\', 't'] => '\t',
+        ['\\', 'r'] => '\r',
+        ['\\', '0'] => '\0',
+        ['\\', ch] if *ch == '\\' || *ch == '\'' || *ch == '"' => *ch,
+        _ => source_error_exit("unsupported character constant in #if expression", origin),
+    };
+    value as i64
+}
+
+// #ifの条件式を評価する
+// conditional = binary ("?" conditional ":" conditional)?
+// binary      = CONDITION_BINARY_OPERATORSの優先順位による左結合の二項演算
+// unary       = ("+" | "-" | "!" | "~") unary | primary
+// primary     = num | "(" conditional ")"
+struct ConditionParser {
+    tokens: Vec<(ConditionToken, SourcePos)>,
+    index: usize,
+    // 短絡評価や?:で値を使わない部分式の深さ, 0でなければ0除算をエラーにしない
+    unevaluated_depth: usize,
+    // 式の途中で終わった場合のエラー位置
+    end_origin: SourcePos,
+}
+
+impl ConditionParser {
+    fn current_origin(&self) -> SourcePos {
+        self.tokens
+            .get(self.index)
+            .map_or(self.end_origin, |(_, origin)| *origin)
+    }
+
+    fn consume(&mut self, punct: &str) -> bool {
+        match self.tokens.get(self.index) {
+            Some((ConditionToken::Punct(token), _)) if *token == punct => {
+                self.index += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, punct: &str) {
+        if !self.consume(punct) {
+            source_error_exit(
+                &format!("expected '{}' in #if expression", punct),
+                self.current_origin(),
+            );
+        }
+    }
+
+    // is_skippedがtrueの場合は, 値を使わない部分式として解析する
+    fn parse_operand<F: FnOnce(&mut ConditionParser) -> i64>(
+        &mut self,
+        is_skipped: bool,
+        parse: F,
+    ) -> i64 {
+        if is_skipped {
+            self.unevaluated_depth += 1;
+        }
+        let value = parse(self);
+        if is_skipped {
+            self.unevaluated_depth -= 1;
+        }
+        value
+    }
+
+    fn conditional(&mut self) -> i64 {
+        let condition = self.binary(0);
+        if !self.consume("?") {
+            return condition;
+        }
+        let then_value = self.parse_operand(condition == 0, |parser| parser.conditional());
+        self.expect(":");
+        let else_value = self.parse_operand(condition != 0, |parser| parser.conditional());
+        if condition != 0 {
+            then_value
+        } else {
+            else_value
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> i64 {
+        if level == CONDITION_BINARY_OPERATORS.len() {
+            return self.unary();
+        }
+        let mut value = self.binary(level + 1);
+        'operators: loop {
+            for operator in CONDITION_BINARY_OPERATORS[level] {
+                let origin = self.current_origin();
+                if self.consume(operator) {
+                    // 左辺で結果が決まる||, &&の右辺は評価しない
+                    let is_skipped =
+                        (*operator == "||" && value != 0) || (*operator == "&&" && value == 0);
+                    let rhs = self.parse_operand(is_skipped, |parser| parser.binary(level + 1));
+                    let is_evaluated = self.unevaluated_depth == 0;
+                    value = apply_condition_operator(operator, value, rhs, origin, is_evaluated);
+                    continue 'operators;
+                }
+            }
+            return value;
+        }
+    }
+
+    fn unary(&mut self) -> i64 {
+        if self.consume("+") {
+            return self.unary();
+        } else if self.consume("-") {
+            return self.unary().wrapping_neg();
+        } else if self.consume("!") {
+            return (self.unary() == 0) as i64;
+        } else if self.consume("~") {
+            return !self.unary();
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> i64 {
+        if self.consume("(") {
+            let value = self.conditional();
+            self.expect(")");
+            return value;
+        }
+        match self.tokens.get(self.index) {
+            Some((ConditionToken::Number(value), _)) => {
+                let value = *value;
+                self.index += 1;
+                value
+            }
+            _ => source_error_exit("expected value in #if expression", self.current_origin()),
+        }
+    }
+}
+
+// 評価しない部分式の0除算はエラーにせず0とする
+fn apply_condition_operator(
+    operator: &str,
+    lhs: i64,
+    rhs: i64,
+    origin: SourcePos,
+    is_evaluated: bool,
+) -> i64 {
+    match operator {
+        "||" => (lhs != 0 || rhs != 0) as i64,
+        "&&" => (lhs != 0 && rhs != 0) as i64,
+        "|" => lhs | rhs,
+        "^" => lhs ^ rhs,
+        "&" => lhs & rhs,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "<" => (lhs < rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 && is_evaluated => {
+            source_error_exit("division by zero in #if expression", origin)
+        }
+        "/" | "%" if rhs == 0 => 0,
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        _ => unreachable!(),
+    }
+}
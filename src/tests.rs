@@ -1,42 +1,54 @@
-#[cfg(test)]
-mod tests {
-    #[cfg(target_arch = "x86_64")]
-    use std::process::Command;
+#[cfg(target_arch = "x86_64")]
+use std::process::Command;
 
-    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    fn make_binary_from_asm() {
-        Command::new("cc")
-            .arg("-o")
-            .arg("./a.out")
-            .arg("tmp.s")
-            .arg("include_func.c")
-            .output()
-            .expect("failed to asemble binary");
-    }
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn make_binary_from_asm() {
+    let status = Command::new("cc")
+        .arg("-o")
+        .arg("./a.out")
+        .arg("tmp.s")
+        .arg("include_func.c")
+        .arg("-lm")
+        .status()
+        .expect("failed to asemble binary");
+    assert!(status.success(), "failed to asemble binary");
+}
 
-    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    fn compare_output() -> i32 {
-        let status = Command::new("sh")
-            .arg("-c")
-            .arg("./a.out")
-            .status()
-            .expect("failed to execute binary")
-            .code()
-            .unwrap();
-        status
-    }
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn compare_output() -> i32 {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg("./a.out")
+        .status()
+        .expect("failed to execute binary")
+        .code()
+        .unwrap();
+    status
+}
 
-    #[test]
-    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    fn compiler_test() {
-        use crate::output_asembly;
-        use std::fs::File;
-        use std::io::{BufRead, BufReader};
+// test/以下の各ファイルは1行目が期待する終了コード, 2行目以降がプログラム
+#[test]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn compiler_test() {
+    use crate::output_asembly;
+    use crate::preprocessor::PreprocessorOptions;
+    use std::fs::{self, File};
+    use std::io::{BufRead, BufReader};
+    use std::path::PathBuf;
 
-        let f = File::open("./test/binary_test.txt").unwrap();
+    let mut test_files: Vec<_> = fs::read_dir("./test")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    test_files.sort();
+
+    let mut failed = false;
+    for test_file in test_files {
+        let f = File::open(&test_file).unwrap();
         let mut lines_iter = BufReader::new(f).lines();
         let correct_output: i32 = lines_iter.next().unwrap().ok().unwrap().parse().unwrap();
-        let mut input_program = format!("");
+        let mut input_program = String::new();
         let mut counter = 0;
         for line_result in lines_iter {
             let line = line_result.unwrap();
@@ -47,17 +59,101 @@ mod tests {
                 input_program = input_program + "\n" + &line;
             }
         }
-        output_asembly(&input_program);
+        // #include <file> はtest/includeから探す
+        let mut options = PreprocessorOptions::new();
+        options.include_paths.push(PathBuf::from("./test/include"));
+        // -D COMMAND_LINE_VALUE=7 -U COMMAND_LINE_UNDEF と同じ
+        options
+            .command_line_directives
+            .push("#define COMMAND_LINE_VALUE 7".to_string());
+        options
+            .command_line_directives
+            .push("#define COMMAND_LINE_UNDEF 1".to_string());
+        options
+            .command_line_directives
+            .push("#undef COMMAND_LINE_UNDEF".to_string());
+        output_asembly(&input_program, &options);
         make_binary_from_asm();
         let result = compare_output();
         if correct_output == result {
-            println!("suceeded!");
+            println!("{}: suceeded!", test_file.display());
+        } else {
+            println!(
+                "{}: test failed! expected {} but {} retuend",
+                test_file.display(),
+                correct_output,
+                result
+            );
+            failed = true;
+        }
+    }
+    if failed {
+        panic!();
+    }
+}
+
+// 子プロセスでコンパイルするファイルを渡す環境変数
+const ERROR_TEST_FILE: &str = "TOY_COMPILER_ERROR_TEST_FILE";
+
+// test/error/以下の各ファイルは1行目が期待するエラーメッセージ, 2行目以降がプログラム
+// エラーはプロセスを終了させるので, このテストのバイナリを子プロセスとして起動してコンパイルする
+#[test]
+fn compile_error_test() {
+    use std::env;
+    use std::fs;
+    use std::process::Command;
+
+    let mut test_files: Vec<_> = fs::read_dir("./test/error")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    test_files.sort();
+
+    let mut failed = false;
+    for test_file in test_files {
+        let text = fs::read_to_string(&test_file).unwrap();
+        let expected_error = text.lines().next().unwrap();
+        let output = Command::new(env::current_exe().unwrap())
+            .arg("tests::compile_error_child")
+            .arg("--exact")
+            .arg("--ignored")
+            .arg("--nocapture")
+            .env(ERROR_TEST_FILE, &test_file)
+            .output()
+            .expect("failed to execute test binary");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if output.status.code() == Some(1) && stdout.contains(expected_error) {
+            println!("{}: suceeded!", test_file.display());
         } else {
             println!(
-                "test failed! expected {} but {} retuend",
-                correct_output, result
+                "{}: test failed! expected error \"{}\" but got\n{}",
+                test_file.display(),
+                expected_error,
+                stdout
             );
-            panic!();
+            failed = true;
         }
     }
+    if failed {
+        panic!();
+    }
+}
+
+// compile_error_testから起動される, エラーがあればここでプロセスが終了する
+#[test]
+#[ignore]
+fn compile_error_child() {
+    use crate::compile_program;
+    use crate::preprocessor::PreprocessorOptions;
+    use std::env;
+    use std::fs;
+
+    let test_file = match env::var(ERROR_TEST_FILE) {
+        Ok(test_file) => test_file,
+        Err(_) => return,
+    };
+    let text = fs::read_to_string(test_file).unwrap();
+    let input_program: Vec<&str> = text.lines().skip(1).collect();
+    compile_program(&input_program.join("\n"), &PreprocessorOptions::new());
 }
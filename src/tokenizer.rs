@@ -1,41 +1,99 @@
 use crate::error::error_exit;
+use crate::types::Type;
+use std::sync::RwLock;
 use std::{collections::VecDeque, iter::FromIterator};
 
-use crate::ast::PrimaryNodeKind;
+use once_cell::sync::Lazy;
 
-use once_cell::sync::OnceCell;
+// 元のファイルでの位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePos {
+    pub file_index: usize,
+    pub pos: usize,
+}
+
+// プリプロセッサが読み込んだファイル, 先頭はコマンドラインで渡したプログラム
+pub struct SourceFile {
+    pub name: String,
+    pub text: Vec<char>,
+}
 
+// トークン化するプリプロセス済みのテキスト
+// エラー表示のため, 各文字が元々あったファイルと位置を持つ
 pub struct ProgramText {
     pub text: Vec<char>,
+    origins: Vec<SourcePos>,
+    files: Vec<SourceFile>,
 }
 
 impl ProgramText {
-    fn new(text: Vec<char>) -> Self {
-        ProgramText { text }
+    pub fn new() -> Self {
+        ProgramText {
+            text: vec![],
+            origins: vec![],
+            files: vec![],
+        }
+    }
+
+    // 読み込んだファイルを登録し, その番号を返す
+    pub fn add_source_file(&mut self, name: String, mut text: Vec<char>) -> usize {
+        if text.last() != Some(&'\n') {
+            text.push('\n');
+        }
+        self.files.push(SourceFile { name, text });
+        self.files.len() - 1
+    }
+
+    pub fn get_source_text(&self, file_index: usize) -> &[char] {
+        &self.files[file_index].text
+    }
+
+    // プリプロセス済みのテキストを設定する, 末尾は改行で終わるようにする
+    pub fn set_text(&mut self, text: Vec<(char, SourcePos)>) {
+        self.text = text.iter().map(|(ch, _)| *ch).collect();
+        self.origins = text.iter().map(|(_, origin)| *origin).collect();
+        if let Some(last_origin) = self.origins.last().copied() {
+            if self.text.last() != Some(&'\n') {
+                self.text.push('\n');
+                self.origins.push(last_origin);
+            }
+        }
     }
 
     pub fn get_tail_pos(&self) -> usize {
         self.text.len() - 1
     }
 
-    pub fn get_error_line(&self, error_pos: usize) -> (String, usize, usize) {
-        let mut error_text = format!("");
+    pub fn get_origin(&self, pos: usize) -> SourcePos {
+        self.origins[pos]
+    }
+
+    // 元のファイルでのエラー発生行を (ファイル名, 行の文字列, 行番号, 列) として返す
+    pub fn get_error_line(&self, origin: SourcePos) -> (String, String, usize, usize) {
+        let source_file = &self.files[origin.file_index];
+        let text = &source_file.text;
+        let error_pos = origin.pos;
+        let mut error_text = String::new();
         let mut pos = error_pos;
 
         // エラー発生行の終端位置を取得
-        while self.text[pos] != '\n' {
+        while text[pos] != '\n' {
             // エラー発生業が最終行の場合
-            if pos != self.text.len() - 1 {
+            if pos != text.len() - 1 {
                 pos += 1;
             } else {
                 break;
             }
         }
+        // 空行の場合は行頭の改行そのものを指している
+        if pos == 0 {
+            return (source_file.name.clone(), error_text, 1, 0);
+        }
         // posはエラー発生行の改行を指しているので, 1つ前に戻す
         pos -= 1;
         // エラー発生行の文字列を取得
-        while self.text[pos] != '\n' {
-            error_text = format!("{}{}", self.text[pos], error_text);
+        while text[pos] != '\n' {
+            error_text = format!("{}{}", text[pos], error_text);
 
             if pos != 0 {
                 pos -= 1;
@@ -45,25 +103,31 @@ impl ProgramText {
         }
         // posはエラー発生行の改行を指しているので, 1つ後ろに戻す
         // ただし先頭行の場合はposが0を指している
-        if pos != 0 {
+        if text[pos] == '\n' {
             pos += 1;
         }
 
         let mut curpos = 0;
         let mut line = 1;
         while curpos != pos {
-            if self.text[curpos] == '\n' {
+            if text[curpos] == '\n' {
                 line += 1;
             }
             curpos += 1;
         }
-        (error_text, line, error_pos - pos)
+        (
+            source_file.name.clone(),
+            error_text,
+            line,
+            error_pos.saturating_sub(pos),
+        )
     }
 }
 
-pub static PROGRAM_TEXT: OnceCell<ProgramText> = OnceCell::new();
+// コンパイルの度にプリプロセッサが入力テキストで置き換える
+pub static PROGRAM_TEXT: Lazy<RwLock<ProgramText>> = Lazy::new(|| RwLock::new(ProgramText::new()));
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OperationKind {
     Gt,
     Ge,
@@ -75,6 +139,16 @@ pub enum OperationKind {
     Sub,
     Mul,
     Div,
+    LogicalAnd,
+    LogicalOr,
+    LogicalNot,
+    Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    Shl,
+    Shr,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -89,25 +163,57 @@ pub enum BracesKind {
     RightBraces,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum BracketsKind {
+    LeftBrackets,
+    RightBrackets,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum TokenKind {
-    Number(i32),
+    Number(i64, Type),      // (値, 接尾辞と大きさから決まる型)
+    FloatNumber(u64, Type), // (f64のビット列, 接尾辞から決まるfloatかdouble)
     Operation(OperationKind),
     Parentheses(ParenthesesKind),
     Braces(BracesKind),
+    Brackets(BracketsKind),
     Comma,
-    LocalVariableDefinition(usize),
-    LocalVariable(usize),
-    FucntionDefinition(String),
+    Identifier(String),
+    StringLiteral(Vec<u8>),
     FucntionCall(String),
     Assign,
+    CompoundAssign(OperationKind), // +=, -= など
+    Increment,
+    Decrement,
+    Void,
+    Int,
+    Char,
+    Short,
+    Long,
+    Unsigned,
+    Signed,
+    Float,
+    Double,
+    Struct,
     Return,
     While,
     If,
     Else,
     For,
+    Do,
+    Switch,
+    Case,
+    Default,
+    Break,
+    Continue,
+    Goto,
+    Sizeof,
     StateMentEnd,
     Reference,
+    Dot,
+    Arrow,
+    Colon,
+    Question,
     InvalidToken,
 }
 
@@ -131,15 +237,11 @@ impl Token {
 #[derive(Debug)]
 pub struct TokenList {
     pub head: Option<Box<Token>>,
-    pub local_stack_size: usize,
 }
 
 impl TokenList {
     fn new() -> TokenList {
-        TokenList {
-            head: None,
-            local_stack_size: 0,
-        }
+        TokenList { head: None }
     }
 
     pub fn peek_head(&self) -> &Option<Box<Token>> {
@@ -149,9 +251,9 @@ impl TokenList {
     pub fn pop_head(&mut self) -> Option<Box<Token>> {
         if let Some(mut token) = self.head.take() {
             self.head = token.next.take();
-            return Some(token);
+            Some(token)
         } else {
-            return None;
+            None
         }
     }
 
@@ -161,196 +263,506 @@ impl TokenList {
 
     pub fn is_operation(&mut self, op: OperationKind) -> bool {
         match self.peek_head() {
-            Some(token) => {
-                if token.token_kind == TokenKind::Operation(op) {
-                    return true;
-                } else {
-                    return false;
-                }
-            }
-            None => {
-                return false;
-            }
+            Some(token) => token.token_kind == TokenKind::Operation(op),
+            None => false,
         }
     }
 
     pub fn consume_operation(&mut self, op: OperationKind) -> bool {
-        match self.peek_head() {
-            Some(token) => {
-                if token.token_kind == TokenKind::Operation(op) {
-                    self.pop_head();
-                    return true;
-                } else {
-                    return false;
-                }
-            }
-            None => {
-                return false;
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Operation(op) {
+                self.pop_head();
+                return true;
             }
         }
+        false
     }
 
     pub fn is_reference(&mut self) -> bool {
         match self.peek_head() {
-            Some(token) => {
-                if token.token_kind == TokenKind::Reference {
-                    return true;
-                } else {
-                    return false;
-                }
-            }
-            None => {
-                return false;
-            }
+            Some(token) => token.token_kind == TokenKind::Reference,
+            None => false,
         }
     }
 
+    pub fn is_commma(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Comma;
+        }
+        false
+    }
+
     pub fn consume_commma(&mut self) -> bool {
-        match self.peek_head() {
-            Some(token) => {
-                if token.token_kind == TokenKind::Comma {
-                    self.pop_head();
-                    return true;
-                } else {
-                    return false;
-                }
-            }
-            None => {
-                return false;
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Comma {
+                self.pop_head();
+                return true;
             }
         }
+        false
     }
 
-    pub fn consume_function_definition(&mut self) -> Option<String> {
+    pub fn consume_functioncall(&mut self) -> Option<String> {
         match self.peek_head() {
             Some(token) => {
                 match token.token_kind {
-                    TokenKind::FucntionDefinition(_) => {
+                    TokenKind::FucntionCall(_) => {
                         // 所有権を取り出し
-                        if let TokenKind::FucntionDefinition(function_name) =
+                        if let TokenKind::FucntionCall(function_name) =
                             self.pop_head().unwrap().token_kind
                         {
                             return Some(function_name);
                         }
-                        return None;
-                    }
-                    _ => {
-                        return None;
+                        None
                     }
+                    _ => None,
                 }
             }
-            None => {
-                return None;
+            None => None,
+        }
+    }
+
+    pub fn is_parentheses(&self, parenthese: ParenthesesKind) -> bool {
+        match self.peek_head() {
+            Some(token) => token.token_kind == TokenKind::Parentheses(parenthese),
+            None => false,
+        }
+    }
+
+    pub fn comsume_parentheses(&mut self, parenthese: ParenthesesKind) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Parentheses(parenthese) {
+                self.pop_head();
+                return true;
             }
         }
+        false
     }
 
-    pub fn consume_functioncall(&mut self) -> Option<String> {
+    pub fn is_braces(&self, braces: BracesKind) -> bool {
         match self.peek_head() {
-            Some(token) => {
-                match token.token_kind {
-                    TokenKind::FucntionCall(_) => {
-                        // 所有権を取り出し
-                        if let TokenKind::FucntionCall(function_name) =
-                            self.pop_head().unwrap().token_kind
-                        {
-                            return Some(function_name);
-                        }
-                        return None;
-                    }
-                    _ => {
-                        return None;
-                    }
-                }
+            Some(token) => token.token_kind == TokenKind::Braces(braces),
+            None => false,
+        }
+    }
+
+    pub fn comsume_braces(&mut self, braces: BracesKind) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Braces(braces) {
+                self.pop_head();
+                return true;
             }
-            None => {
-                return None;
+        }
+        false
+    }
+
+    pub fn is_brackets(&self, brackets: BracketsKind) -> bool {
+        match self.peek_head() {
+            Some(token) => token.token_kind == TokenKind::Brackets(brackets),
+            None => false,
+        }
+    }
+
+    pub fn consume_brackets(&mut self, brackets: BracketsKind) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Brackets(brackets) {
+                self.pop_head();
+                return true;
             }
         }
+        false
     }
 
-    pub fn is_parentheses(&self, parenthese: ParenthesesKind) -> bool {
+    // 複合代入演算子の場合は対応する二項演算を返す
+    pub fn is_compound_assign(&self) -> Option<OperationKind> {
+        if let Some(first_token) = self.peek_head() {
+            if let TokenKind::CompoundAssign(operation) = first_token.token_kind {
+                return Some(operation);
+            }
+        }
+        None
+    }
+
+    pub fn is_increment(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Increment;
+        }
+        false
+    }
+
+    pub fn is_decrement(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Decrement;
+        }
+        false
+    }
+
+    pub fn is_assign(&self) -> bool {
         match self.peek_head() {
-            Some(token) => {
-                if token.token_kind == TokenKind::Parentheses(parenthese) {
-                    return true;
-                } else {
-                    return false;
-                }
+            Some(token) => token.token_kind == TokenKind::Assign,
+            None => false,
+        }
+    }
+
+    pub fn consume_return(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Return {
+                self.pop_head();
+                return true;
+            }
+        }
+        false
+    }
+
+    // if文かチェック
+    pub fn consume_if(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::If {
+                self.pop_head();
+                return true;
             }
+        }
+        false
+    }
+
+    // else文かチェック
+    pub fn consume_else(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Else {
+                self.pop_head();
+                return true;
+            }
+        }
+        false
+    }
+
+    // while文かチェック
+    pub fn consume_while(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::While {
+                self.pop_head();
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn consume_do(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Do {
+                self.pop_head();
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn consume_switch(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Switch {
+                self.pop_head();
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn consume_colon(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Colon {
+                self.pop_head();
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn is_question(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Question;
+        }
+        false
+    }
+
+    pub fn is_case(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Case;
+        }
+        false
+    }
+
+    pub fn is_default(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Default;
+        }
+        false
+    }
+
+    // for文かチェック
+    pub fn consume_for(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::For {
+                self.pop_head();
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn is_statement_end(&self) -> bool {
+        match self.peek_head() {
+            Some(token) => token.token_kind == TokenKind::StateMentEnd,
+            None => false,
+        }
+    }
+
+    pub fn consume_statement_end(&mut self) -> bool {
+        let token = self.pop_head();
+        let error_text = "expect ; at end of statement";
+        match token {
+            Some(valid_token) => match valid_token.token_kind {
+                TokenKind::StateMentEnd => true,
+                _ => {
+                    error_exit(error_text, valid_token.token_pos);
+                }
+            },
+            // Noneの場合はトークン終了を意味する
             None => {
-                return false;
+                // テキスト終端の1つ後ろに要求エラーを立てる
+                let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
+                error_exit(error_text, tail_pos);
             }
         }
     }
 
-    pub fn comsume_parentheses(&mut self, parenthese: ParenthesesKind) -> bool {
+    pub fn consume_number(&mut self) -> Option<i64> {
+        self.consume_number_literal().map(|(num, _)| num)
+    }
+
+    // 整数リテラルの値と型を取り出す
+    pub fn consume_number_literal(&mut self) -> Option<(i64, Type)> {
         match self.peek_head() {
             Some(token) => {
-                if token.token_kind == TokenKind::Parentheses(parenthese) {
+                if let TokenKind::Number(num, ty) = &token.token_kind {
+                    let literal = (*num, ty.clone());
                     self.pop_head();
-                    return true;
+                    Some(literal)
                 } else {
-                    return false;
+                    None
                 }
             }
-            None => {
-                return false;
-            }
+            None => None,
         }
     }
 
-    pub fn comsume_braces(&mut self, braces: BracesKind) -> bool {
+    // 浮動小数点数リテラルの値と型を取り出す
+    pub fn consume_float_literal(&mut self) -> Option<(f64, Type)> {
         match self.peek_head() {
             Some(token) => {
-                if token.token_kind == TokenKind::Braces(braces) {
+                if let TokenKind::FloatNumber(bits, ty) = &token.token_kind {
+                    let literal = (f64::from_bits(*bits), ty.clone());
                     self.pop_head();
-                    return true;
+                    Some(literal)
                 } else {
-                    return false;
+                    None
                 }
             }
+            None => None,
+        }
+    }
+
+    // 識別子の場合は識別子名とテキスト中の位置を返す
+    pub fn is_identifier(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return matches!(first_token.token_kind, TokenKind::Identifier(_));
+        }
+        false
+    }
+
+    pub fn consume_identifier(&mut self) -> Option<(String, usize)> {
+        match self.peek_head() {
+            Some(token) => match token.token_kind {
+                TokenKind::Identifier(_) => {
+                    // 所有権を取り出し
+                    let identifier_token = self.pop_head().unwrap();
+                    if let TokenKind::Identifier(identifier) = identifier_token.token_kind {
+                        return Some((identifier, identifier_token.token_pos));
+                    }
+                    None
+                }
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn expect_identifier(&mut self) -> (String, usize) {
+        if let Some(identifier) = self.consume_identifier() {
+            return identifier;
+        }
+        let error_text = "expect identifier";
+        match self.pop_head() {
+            Some(valid_token) => {
+                error_exit(error_text, valid_token.token_pos);
+            }
+            // Noneの場合はトークン終了を意味する
             None => {
-                return false;
+                // テキスト終端の1つ後ろに要求エラーを立てる
+                let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
+                error_exit(error_text, tail_pos);
+            }
+        }
+    }
+
+    // 型名で始まるかチェック
+    pub fn is_type_name(&self) -> bool {
+        match self.peek_head() {
+            Some(token) => is_type_name_token(&token.token_kind),
+            None => false,
+        }
+    }
+
+    // "(" の次が型名かチェック, sizeof(型名)の判定に使う
+    pub fn is_parenthesized_type_name(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Parentheses(ParenthesesKind::LeftParentheses) {
+                if let Some(second_token) = &first_token.next {
+                    return is_type_name_token(&second_token.token_kind);
+                }
+            }
+        }
+        false
+    }
+
+    // "(" "*"+ ")" で始まるかチェック, int (*)(int) のような名前のない関数ポインタ型の判定に使う
+    pub fn is_abstract_function_pointer(&self) -> bool {
+        let mut token = match self.peek_head() {
+            Some(first_token)
+                if first_token.token_kind
+                    == TokenKind::Parentheses(ParenthesesKind::LeftParentheses) =>
+            {
+                &first_token.next
+            }
+            _ => return false,
+        };
+        let mut pointer_count = 0;
+        while let Some(next_token) = token {
+            match next_token.token_kind {
+                TokenKind::Operation(OperationKind::Mul) => pointer_count += 1,
+                TokenKind::Parentheses(ParenthesesKind::RightParentheses) => {
+                    return pointer_count > 0;
+                }
+                _ => return false,
+            }
+            token = &next_token.next;
+        }
+        false
+    }
+
+    pub fn is_goto(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Goto;
+        }
+        false
+    }
+
+    // 識別子の次が:であればラベル
+    pub fn is_label(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if let TokenKind::Identifier(_) = first_token.token_kind {
+                if let Some(second_token) = &first_token.next {
+                    return second_token.token_kind == TokenKind::Colon;
+                }
+            }
+        }
+        false
+    }
+
+    pub fn is_break(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Break;
+        }
+        false
+    }
+
+    pub fn is_continue(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Continue;
+        }
+        false
+    }
+
+    pub fn is_sizeof(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Sizeof;
+        }
+        false
+    }
+
+    pub fn consume_struct(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Struct {
+                self.pop_head();
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn is_dot(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Dot;
+        }
+        false
+    }
+
+    pub fn is_arrow(&self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            return first_token.token_kind == TokenKind::Arrow;
+        }
+        false
+    }
+
+    pub fn consume_int(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Int {
+                self.pop_head();
+                return true;
             }
         }
+        false
     }
 
-    pub fn is_assign(&self) -> bool {
-        match self.peek_head() {
-            Some(token) => {
-                if token.token_kind == TokenKind::Assign {
-                    return true;
-                } else {
-                    return false;
-                }
-            }
-            None => {
-                return false;
+    pub fn consume_void(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Void {
+                self.pop_head();
+                return true;
             }
         }
+        false
     }
 
-    pub fn consume_return(&mut self) -> bool {
-        match self.peek_head() {
-            Some(token) => {
-                if token.token_kind == TokenKind::Return {
-                    self.pop_head();
-                    return true;
-                } else {
-                    return false;
+    // f(void)のように引数がないことを示すvoid
+    pub fn consume_void_arguments(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Void {
+                if let Some(second_token) = &first_token.next {
+                    if second_token.token_kind
+                        == TokenKind::Parentheses(ParenthesesKind::RightParentheses)
+                    {
+                        self.pop_head();
+                        return true;
+                    }
                 }
             }
-            None => {
-                return false;
-            }
         }
+        false
     }
 
-    // if文かチェック
-    pub fn consume_if(&mut self) -> bool {
+    pub fn consume_short(&mut self) -> bool {
         if let Some(first_token) = self.peek_head() {
-            if first_token.token_kind == TokenKind::If {
+            if first_token.token_kind == TokenKind::Short {
                 self.pop_head();
                 return true;
             }
@@ -358,10 +770,9 @@ impl TokenList {
         false
     }
 
-    // else文かチェック
-    pub fn consume_else(&mut self) -> bool {
+    pub fn consume_long(&mut self) -> bool {
         if let Some(first_token) = self.peek_head() {
-            if first_token.token_kind == TokenKind::Else {
+            if first_token.token_kind == TokenKind::Long {
                 self.pop_head();
                 return true;
             }
@@ -369,10 +780,9 @@ impl TokenList {
         false
     }
 
-    // while文かチェック
-    pub fn consume_while(&mut self) -> bool {
+    pub fn consume_unsigned(&mut self) -> bool {
         if let Some(first_token) = self.peek_head() {
-            if first_token.token_kind == TokenKind::While {
+            if first_token.token_kind == TokenKind::Unsigned {
                 self.pop_head();
                 return true;
             }
@@ -380,10 +790,9 @@ impl TokenList {
         false
     }
 
-    // for文かチェック
-    pub fn consume_for(&mut self) -> bool {
+    pub fn consume_signed(&mut self) -> bool {
         if let Some(first_token) = self.peek_head() {
-            if first_token.token_kind == TokenKind::For {
+            if first_token.token_kind == TokenKind::Signed {
                 self.pop_head();
                 return true;
             }
@@ -391,107 +800,100 @@ impl TokenList {
         false
     }
 
-    pub fn is_statement_end(&self) -> bool {
-        match self.peek_head() {
-            Some(token) => {
-                if token.token_kind == TokenKind::StateMentEnd {
-                    return true;
-                } else {
-                    return false;
-                }
-            }
-            None => {
-                return false;
+    pub fn consume_float(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Float {
+                self.pop_head();
+                return true;
             }
         }
+        false
     }
 
-    pub fn consume_statement_end(&mut self) -> bool {
-        let token = self.pop_head();
-        let error_text = "expect ; at end of statement";
-        match token {
-            Some(valid_token) => match valid_token.token_kind {
-                TokenKind::StateMentEnd => {
-                    return true;
-                }
-                _ => {
-                    error_exit(error_text, valid_token.token_pos);
-                }
-            },
-            // Noneの場合はトークン終了を意味する
-            None => {
-                // テキスト終端の1つ後ろに要求エラーを立てる
-                let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
-                error_exit(error_text, tail_pos);
+    pub fn consume_double(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Double {
+                self.pop_head();
+                return true;
             }
         }
+        false
     }
 
-    pub fn expect_primary(&mut self) -> PrimaryNodeKind {
-        let token = self.pop_head();
-        let error_text = "expect number or variable token";
-        match token {
-            Some(valid_token) => match valid_token.token_kind {
-                TokenKind::Number(num) => {
-                    return PrimaryNodeKind::Number(num);
-                }
-                TokenKind::LocalVariable(offset) => {
-                    return PrimaryNodeKind::LocalVariable(offset);
-                }
-                _ => {
-                    error_exit(error_text, valid_token.token_pos);
-                }
-            },
-            // Noneの場合はトークン終了を意味する
-            None => {
-                // テキスト終端の1つ後ろに要求エラーを立てる
-                let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
-                error_exit(error_text, tail_pos);
+    pub fn consume_char(&mut self) -> bool {
+        if let Some(first_token) = self.peek_head() {
+            if first_token.token_kind == TokenKind::Char {
+                self.pop_head();
+                return true;
             }
         }
+        false
     }
 
-    pub fn expect_variable_definition(&mut self) -> PrimaryNodeKind {
-        let token = self.pop_head();
-        let error_text = "expect variable token";
-        match token {
-            Some(valid_token) => match valid_token.token_kind {
-                TokenKind::LocalVariableDefinition(offset) => {
-                    return PrimaryNodeKind::LocalVariable(offset);
-                }
-                _ => {
-                    error_exit(error_text, valid_token.token_pos);
+    // 文字列リテラルの場合はNUL終端を含まないバイト列を返す
+    pub fn consume_string_literal(&mut self) -> Option<Vec<u8>> {
+        match self.peek_head() {
+            Some(token) => match token.token_kind {
+                TokenKind::StringLiteral(_) => {
+                    // 所有権を取り出し
+                    if let TokenKind::StringLiteral(bytes) = self.pop_head().unwrap().token_kind {
+                        return Some(bytes);
+                    }
+                    None
                 }
+                _ => None,
             },
-            // Noneの場合はトークン終了を意味する
-            None => {
-                // テキスト終端の1つ後ろに要求エラーを立てる
-                let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
-                error_exit(error_text, tail_pos);
-            }
+            None => None,
         }
     }
 }
 
 impl Drop for TokenList {
     fn drop(&mut self) {
+        // 再帰的なdropでスタックが溢れないよう, 先頭から順に切り離す
         let mut token = self.head.take();
-        loop {
-            match token {
-                Some(mut valid_token) => {
-                    let next_token = valid_token.next.take();
-                    token = next_token;
-                }
-                None => {
-                    break;
-                }
-            }
+        while let Some(mut valid_token) = token {
+            token = valid_token.next.take();
         }
     }
 }
 
+// 複合代入演算子に対応する二項演算
+fn compound_assign_operation(op_string: &str) -> Option<OperationKind> {
+    match op_string {
+        "+=" => Some(OperationKind::Add),
+        "-=" => Some(OperationKind::Sub),
+        "*=" => Some(OperationKind::Mul),
+        "/=" => Some(OperationKind::Div),
+        "%=" => Some(OperationKind::Mod),
+        "&=" => Some(OperationKind::BitAnd),
+        "|=" => Some(OperationKind::BitOr),
+        "^=" => Some(OperationKind::BitXor),
+        "<<=" => Some(OperationKind::Shl),
+        ">>=" => Some(OperationKind::Shr),
+        _ => None,
+    }
+}
+
+// 型名の先頭になるトークンか
+fn is_type_name_token(token_kind: &TokenKind) -> bool {
+    matches!(
+        token_kind,
+        TokenKind::Void
+            | TokenKind::Int
+            | TokenKind::Char
+            | TokenKind::Short
+            | TokenKind::Long
+            | TokenKind::Unsigned
+            | TokenKind::Signed
+            | TokenKind::Float
+            | TokenKind::Double
+            | TokenKind::Struct
+    )
+}
+
 fn is_operational_char(ch: &char) -> bool {
-    if *ch == '='
+    *ch == '='
         || *ch == '+'
         || *ch == '-'
         || *ch == '*'
@@ -506,32 +908,171 @@ fn is_operational_char(ch: &char) -> bool {
         || *ch == '\n'
         || *ch == ','
         || *ch == ' '
+        || *ch == '\t'
+        || *ch == '\r'
         || *ch == '&'
-    {
-        true
+        || *ch == '|'
+        || *ch == '!'
+        || *ch == '<'
+        || *ch == '>'
+        || *ch == '%'
+        || *ch == '^'
+        || *ch == ':'
+        || *ch == '?'
+}
+
+// 整数リテラルと接尾辞(u, l, ul, ll, ull)を取り出す
+// 0xで始まれば16進数, 0bで始まれば2進数, 0で始まれば8進数として読む
+// 接尾辞がない場合はintに収まらなければlong, uの場合はunsigned intに収まらなければunsigned longとする
+// 10進数以外はunsignedで収まる場合はunsignedの型になる
+fn pop_digit(char_queue: &mut VecDeque<char>, token_pos: usize) -> Result<(i64, Type), ()> {
+    let mut radix = 10;
+    if char_queue.front() == Some(&'0') {
+        match char_queue.get(1) {
+            Some('x') | Some('X') => radix = 16,
+            Some('b') | Some('B') => radix = 2,
+            _ => radix = 8,
+        }
+        if radix != 8 {
+            char_queue.pop_front();
+            char_queue.pop_front();
+        }
+    }
+
+    let mut num: u64 = 0;
+    let mut digit_count = 0;
+    let mut is_overflow = false;
+    // 8進数の8, 9や2進数の2以上は数字として読んでからエラーにする
+    let read_radix = if radix == 16 { 16 } else { 10 };
+    while let Some(next_ch) = char_queue.front() {
+        let digit = match next_ch.to_digit(read_radix) {
+            Some(digit) => digit as u64,
+            None => break,
+        };
+        if digit >= radix as u64 {
+            error_exit(
+                &format!("invalid digit {} in integer literal", next_ch),
+                token_pos,
+            );
+        }
+        char_queue.pop_front();
+        digit_count += 1;
+        match num
+            .checked_mul(radix as u64)
+            .and_then(|num| num.checked_add(digit))
+        {
+            Some(next_num) => num = next_num,
+            None => is_overflow = true,
+        }
+    }
+    if digit_count == 0 {
+        error_exit("integer literal has no digits", token_pos);
+    }
+
+    let mut suffix = String::new();
+    while let Some(next_ch) = char_queue.front() {
+        if !next_ch.is_ascii_alphanumeric() && *next_ch != '_' {
+            break;
+        }
+        suffix.push(char_queue.pop_front().unwrap().to_ascii_lowercase());
+    }
+    if let Some(next_ch) = char_queue.front() {
+        if !is_operational_char(next_ch) {
+            return Err(());
+        }
+    }
+    let (is_unsigned, is_long) = match suffix.as_str() {
+        "" => (false, false),
+        "u" => (true, false),
+        "l" | "ll" => (false, true),
+        "ul" | "lu" | "ull" | "llu" => (true, true),
+        _ => return Err(()),
+    };
+
+    let is_decimal = radix == 10;
+    let ty = if is_unsigned && is_long {
+        Type::ULong
+    } else if is_unsigned {
+        if num <= u32::MAX as u64 {
+            Type::UInt
+        } else {
+            Type::ULong
+        }
+    } else if !is_long && num <= i32::MAX as u64 {
+        Type::Int
+    } else if !is_long && !is_decimal && num <= u32::MAX as u64 {
+        Type::UInt
+    } else if num <= i64::MAX as u64 {
+        Type::Long
+    } else if !is_decimal {
+        Type::ULong
     } else {
-        false
+        is_overflow = true;
+        Type::Long
+    };
+    if is_overflow {
+        error_exit("integer literal is too large", token_pos);
     }
+    Ok((num as i64, ty))
 }
 
-fn pop_digit(char_queue: &mut VecDeque<char>) -> Result<i32, ()> {
-    let mut num = char_queue.pop_front().unwrap().to_digit(10).unwrap() as i32;
-    loop {
-        let next = char_queue.front();
-        if let Some(next_ch) = next {
-            if next_ch.is_digit(10) {
-                let next_digit = char_queue.pop_front().unwrap().to_digit(10).unwrap() as i32;
-                num = num * 10 + next_digit;
-            } else if is_operational_char(next_ch) {
-                break;
-            } else {
-                return Err(());
+// 数字の後に.か指数部が続く10進数, または.と数字で始まるものは浮動小数点数リテラル
+fn is_float_literal(char_queue: &VecDeque<char>) -> bool {
+    if char_queue.front() == Some(&'0')
+        && matches!(
+            char_queue.get(1),
+            Some('x') | Some('X') | Some('b') | Some('B')
+        )
+    {
+        return false;
+    }
+    let digits_end = char_queue
+        .iter()
+        .position(|ch| !ch.is_ascii_digit())
+        .unwrap_or(char_queue.len());
+    match char_queue.get(digits_end) {
+        Some('.') => digits_end > 0 || char_queue.get(1).is_some_and(|ch| ch.is_ascii_digit()),
+        Some('e') | Some('E') => digits_end > 0,
+        _ => false,
+    }
+}
+
+// 浮動小数点数リテラルと接尾辞(f, l)を取り出す
+// 接尾辞がない場合とlの場合はdouble, fの場合はfloatとする
+fn pop_float(char_queue: &mut VecDeque<char>, token_pos: usize) -> Result<(f64, Type), ()> {
+    let mut literal = String::new();
+    while let Some(next_ch) = char_queue.front() {
+        if next_ch.is_ascii_digit() || *next_ch == '.' {
+            literal.push(char_queue.pop_front().unwrap());
+        } else if *next_ch == 'e' || *next_ch == 'E' {
+            literal.push(char_queue.pop_front().unwrap());
+            if let Some('+') | Some('-') = char_queue.front() {
+                literal.push(char_queue.pop_front().unwrap());
             }
         } else {
             break;
         }
     }
-    Ok(num)
+    let ty = match char_queue.front() {
+        Some('f') | Some('F') => {
+            char_queue.pop_front();
+            Type::Float
+        }
+        Some('l') | Some('L') => {
+            char_queue.pop_front();
+            Type::Double
+        }
+        _ => Type::Double,
+    };
+    if let Some(next_ch) = char_queue.front() {
+        if !is_operational_char(next_ch) {
+            return Err(());
+        }
+    }
+    match literal.parse::<f64>() {
+        Ok(value) => Ok((value, ty)),
+        Err(_) => error_exit("invalid floating point literal", token_pos),
+    }
 }
 
 fn pop_operation(char_queue: &mut VecDeque<char>) -> TokenKind {
@@ -546,13 +1087,39 @@ fn pop_operation(char_queue: &mut VecDeque<char>) -> TokenKind {
         return TokenKind::Braces(BracesKind::LeftBraces);
     } else if op_string == "}" {
         return TokenKind::Braces(BracesKind::RightBraces);
+    } else if op_string == "[" {
+        return TokenKind::Brackets(BracketsKind::LeftBrackets);
+    } else if op_string == "]" {
+        return TokenKind::Brackets(BracketsKind::RightBrackets);
     } else if op_string == ";" {
         return TokenKind::StateMentEnd;
     } else if op_string == "," {
         return TokenKind::Comma;
+    } else if op_string == "." {
+        return TokenKind::Dot;
+    } else if op_string == ":" {
+        return TokenKind::Colon;
+    } else if op_string == "?" {
+        return TokenKind::Question;
+    } else if op_string == "-" && char_queue.front() == Some(&'>') {
+        char_queue.pop_front();
+        return TokenKind::Arrow;
+    }
+
+    // &&, ||, <<, >>, ++, -- は同じ文字を2つ続ける
+    if (op_string == "&"
+        || op_string == "|"
+        || op_string == "<"
+        || op_string == ">"
+        || op_string == "+"
+        || op_string == "-")
+        && char_queue.front() == Some(&ch)
+    {
+        char_queue.pop_front();
+        op_string.push(ch);
     }
 
-    // <、<=、>、>=、==、!= に対応するため, 次が=ならばそれも取り出す
+    // <、<=、>、>=、==、!=, +=などの複合代入に対応するため, 次が=ならばそれも取り出す
     if let Some(next_ch_ref) = char_queue.front() {
         if *next_ch_ref == '=' {
             let next_ch = char_queue.pop_front().unwrap();
@@ -561,6 +1128,12 @@ fn pop_operation(char_queue: &mut VecDeque<char>) -> TokenKind {
     }
     if op_string == "=" {
         TokenKind::Assign
+    } else if op_string == "++" {
+        TokenKind::Increment
+    } else if op_string == "--" {
+        TokenKind::Decrement
+    } else if let Some(operation) = compound_assign_operation(&op_string) {
+        TokenKind::CompoundAssign(operation)
     } else if op_string == ">" {
         TokenKind::Operation(OperationKind::Gt)
     } else if op_string == ">=" {
@@ -583,83 +1156,169 @@ fn pop_operation(char_queue: &mut VecDeque<char>) -> TokenKind {
         TokenKind::Operation(OperationKind::Div)
     } else if op_string == "&" {
         TokenKind::Reference
+    } else if op_string == "&&" {
+        TokenKind::Operation(OperationKind::LogicalAnd)
+    } else if op_string == "||" {
+        TokenKind::Operation(OperationKind::LogicalOr)
+    } else if op_string == "!" {
+        TokenKind::Operation(OperationKind::LogicalNot)
+    } else if op_string == "%" {
+        TokenKind::Operation(OperationKind::Mod)
+    } else if op_string == "|" {
+        TokenKind::Operation(OperationKind::BitOr)
+    } else if op_string == "^" {
+        TokenKind::Operation(OperationKind::BitXor)
+    } else if op_string == "~" {
+        TokenKind::Operation(OperationKind::BitNot)
+    } else if op_string == "<<" {
+        TokenKind::Operation(OperationKind::Shl)
+    } else if op_string == ">>" {
+        TokenKind::Operation(OperationKind::Shr)
     } else {
         TokenKind::InvalidToken
     }
 }
 
-fn pop_identifier(char_queue: &mut VecDeque<char>,) -> String {
-    let ch = char_queue.pop_front().unwrap();
-    let mut identifier = format!("{}", ch);
-
-    // ascii, _, 0~9 が続くうちは取り出す
+// "で囲まれた文字列をエスケープを解決したバイト列として取り出す
+// 閉じられていない場合はErrを返す
+fn pop_string_literal(char_queue: &mut VecDeque<char>, token_pos: usize) -> Result<Vec<u8>, ()> {
+    // 先頭の"を取り出し
+    char_queue.pop_front();
+    let mut bytes = vec![];
     loop {
-        if let Some(next_ch_ref) = char_queue.front() {
-            if next_ch_ref.is_ascii_alphabetic() || *next_ch_ref == '_' || next_ch_ref.is_digit(10)
-            {
-                let next_ch = char_queue.pop_front().unwrap();
-                identifier.push(next_ch);
-            } else {
-                break;
+        let ch = match char_queue.pop_front() {
+            Some('"') => {
+                return Ok(bytes);
             }
-        } else {
-            break;
-        }
+            Some('\\') => {
+                bytes.push(pop_escape_sequence(char_queue, token_pos)?);
+                continue;
+            }
+            Some(ch) if ch != '\n' => ch,
+            _ => {
+                return Err(());
+            }
+        };
+        let mut buf = [0; 4];
+        bytes.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
     }
-    identifier
 }
 
-fn pop_identifier_token(
-    char_queue: &mut VecDeque<char>,
-    local_variable_set: &mut Vec<String>,
-    tokenizer_info: &mut TokenizerInfo
-) -> TokenKind {
-
-    let text_len = PROGRAM_TEXT.get().unwrap().get_tail_pos();
-    let mut indentifier_head_pos = text_len - char_queue.len();
-
-    let mut identifier = pop_identifier(char_queue);
-    let mut is_variable_definition = false;
-
-    if identifier == "int" {
-        skip_input(char_queue);
-        if char_queue.is_empty() {
-            error_exit("variable definition is not correct", text_len);
-        }
-
-        let ch =char_queue.front().unwrap();
-        if !ch.is_ascii_alphabetic() {
-            return TokenKind::InvalidToken;
+// 文字リテラルはint型の値になる
+// charは符号付きなので, '\xff'は-1になる
+fn pop_char_literal(char_queue: &mut VecDeque<char>, token_pos: usize) -> Result<i64, ()> {
+    // 先頭の'を取り出し
+    char_queue.pop_front();
+    let value = match char_queue.pop_front() {
+        Some('\\') => pop_escape_sequence(char_queue, token_pos)?,
+        Some(ch) if ch != '\'' && ch != '\n' => {
+            if !ch.is_ascii() {
+                error_exit("multibyte character literal is not supported", token_pos);
+            }
+            ch as u8
         }
-
-        indentifier_head_pos = text_len - char_queue.len();
-
-        identifier = pop_identifier(char_queue);
-        skip_input(char_queue);
-        if char_queue.is_empty() {
-            error_exit("variable definition is not correct", text_len);
+        _ => {
+            return Err(());
         }
+    };
+    if char_queue.pop_front() != Some('\'') {
+        return Err(());
+    }
+    Ok(value as i8 as i64)
+}
 
-        let ch =char_queue.front().unwrap();
-        if tokenizer_info.state == TokenizerState::FucntionDefinition {
-            if !(*ch == ')'  || *ch == ',') {
-                error_exit("variable definition needs ','", text_len - char_queue.len());
-            }
-        } else {
-            if *ch != ';' {
-                error_exit("variable definition needs ';'", text_len - char_queue.len());
-            } else {
+// \に続くエスケープシーケンスを1バイトの値として取り出す
+// \x41のような16進数と\101のような8進数も扱い, 1バイトに収まらない場合はtoken_posにエラーを立てる
+fn pop_escape_sequence(char_queue: &mut VecDeque<char>, token_pos: usize) -> Result<u8, ()> {
+    let value = match char_queue.pop_front() {
+        Some('n') => '\n' as u32,
+        Some('t') => '\t' as u32,
+        Some('r') => '\r' as u32,
+        Some('a') => 0x07,
+        Some('b') => 0x08,
+        Some('f') => 0x0c,
+        Some('v') => 0x0b,
+        Some('x') => {
+            let mut value: u32 = 0;
+            let mut digit_count = 0;
+            while let Some(digit) = char_queue.front().and_then(|ch| ch.to_digit(16)) {
                 char_queue.pop_front();
+                value = value.saturating_mul(16).saturating_add(digit);
+                digit_count += 1;
+            }
+            if digit_count == 0 {
+                error_exit("\\x used with no following hex digits", token_pos);
+            }
+            value
+        }
+        // 8進数は3桁まで
+        Some(ch) if ch.is_digit(8) => {
+            let mut value = ch.to_digit(8).unwrap();
+            for _ in 0..2 {
+                match char_queue.front().and_then(|ch| ch.to_digit(8)) {
+                    Some(digit) => {
+                        char_queue.pop_front();
+                        value = value * 8 + digit;
+                    }
+                    None => break,
+                }
             }
+            value
+        }
+        // \\, \', \"などはその文字自身
+        Some(ch) if ch != '\n' => ch as u32,
+        _ => {
+            return Err(());
         }
-        is_variable_definition = true;
+    };
+    if value > 0xff {
+        error_exit("escape sequence out of range", token_pos);
     }
+    Ok(value as u8)
+}
+
+fn pop_identifier(char_queue: &mut VecDeque<char>) -> String {
+    let ch = char_queue.pop_front().unwrap();
+    let mut identifier = format!("{}", ch);
 
-    if identifier == "int" {
-        error_exit("cannot use int for variable name", indentifier_head_pos);
+    // ascii, _, 0~9 が続くうちは取り出す
+    while let Some(next_ch_ref) = char_queue.front() {
+        if next_ch_ref.is_ascii_alphabetic() || *next_ch_ref == '_' || next_ch_ref.is_ascii_digit()
+        {
+            let next_ch = char_queue.pop_front().unwrap();
+            identifier.push(next_ch);
+        } else {
+            break;
+        }
     }
+    identifier
+}
+
+// 変数の宣言と名前解決はAST側で行うので, ここでは識別子か予約語かだけを判定する
+fn pop_identifier_token(char_queue: &mut VecDeque<char>) -> TokenKind {
+    let identifier = pop_identifier(char_queue);
 
-    if identifier == "return" {
+    if identifier == "void" {
+        return TokenKind::Void;
+    } else if identifier == "int" {
+        return TokenKind::Int;
+    } else if identifier == "char" {
+        return TokenKind::Char;
+    } else if identifier == "short" {
+        return TokenKind::Short;
+    } else if identifier == "long" {
+        return TokenKind::Long;
+    } else if identifier == "unsigned" {
+        return TokenKind::Unsigned;
+    } else if identifier == "signed" {
+        return TokenKind::Signed;
+    } else if identifier == "float" {
+        return TokenKind::Float;
+    } else if identifier == "double" {
+        return TokenKind::Double;
+    } else if identifier == "struct" {
+        return TokenKind::Struct;
+    } else if identifier == "return" {
         return TokenKind::Return;
     } else if identifier == "while" {
         return TokenKind::While;
@@ -669,10 +1328,27 @@ fn pop_identifier_token(
         return TokenKind::Else;
     } else if identifier == "for" {
         return TokenKind::For;
+    } else if identifier == "do" {
+        return TokenKind::Do;
+    } else if identifier == "switch" {
+        return TokenKind::Switch;
+    } else if identifier == "case" {
+        return TokenKind::Case;
+    } else if identifier == "default" {
+        return TokenKind::Default;
+    } else if identifier == "goto" {
+        return TokenKind::Goto;
+    } else if identifier == "break" {
+        return TokenKind::Break;
+    } else if identifier == "continue" {
+        return TokenKind::Continue;
+    } else if identifier == "sizeof" {
+        return TokenKind::Sizeof;
     }
 
     skip_input(char_queue);
     if char_queue.is_empty() {
+        let text_len = PROGRAM_TEXT.read().unwrap().get_tail_pos();
         error_exit("program is not correct", text_len);
     }
 
@@ -680,68 +1356,12 @@ fn pop_identifier_token(
     if *ch == '(' {
         return TokenKind::FucntionCall(identifier);
     }
-
-    for (index, exist_variable) in local_variable_set.iter().enumerate() {
-        if *exist_variable == identifier {
-            if is_variable_definition {
-                error_exit(&format!("variable {} is already defined", identifier), indentifier_head_pos);
-            }
-            return TokenKind::LocalVariable((index + 1) * 8);
-        }
-    }
-    if is_variable_definition {
-        local_variable_set.push(identifier);
-        return TokenKind::LocalVariableDefinition(local_variable_set.len() * 8);
-    } else {
-        error_exit(&format!("undefined variable {}", identifier), indentifier_head_pos);
-    }
-}
-
-fn pop_function_definition_token(char_queue: &mut VecDeque<char>) -> Result<String, String> {
-    let identifier = pop_identifier(char_queue);
-
-    if identifier != "int" {
-        return Err(format!("function must return int"));
-    }
-
-    skip_input(char_queue);
-
-    let mut function_name = format!("");
-    // ascii, _, 0~9 が続くうちは取り出す
-    loop {
-        if let Some(next_ch_ref) = char_queue.front() {
-            if next_ch_ref.is_ascii_alphabetic() || *next_ch_ref == '_' || next_ch_ref.is_digit(10)
-            {
-                let next_ch = char_queue.pop_front().unwrap();
-                function_name.push(next_ch);
-            } else {
-                break;
-            }
-        } else {
-            return Err(format!("function definition is not correct"));
-        }
-    }
-
-    if function_name == "return" {
-        return Err(format!("return is invalid function name"));
-    } else if function_name == "while" {
-        return Err(format!("while is invalid function name"));
-    } else if function_name == "if" {
-        return Err(format!("if is invalid function name"));
-    } else if function_name == "else" {
-        return Err(format!("else is invalid function name"));
-    } else if function_name == "for" {
-        return Err(format!("for is invalid function name"));
-    } else if function_name == "int" {
-        return Err(format!("int is invalid function name"));
-    } 
-
-    return Ok(function_name);
+    TokenKind::Identifier(identifier)
 }
 
 // プログラム文終端までコメントの場合はOk(true)を返す
 fn skip_comment(char_queue: &mut VecDeque<char>) -> Result<(), ()> {
-    if let Some(ch0) = char_queue.get(0) {
+    if let Some(ch0) = char_queue.front() {
         if *ch0 == '/' {
             if let Some(ch1) = char_queue.get(1) {
                 if *ch1 == '/' {
@@ -763,7 +1383,7 @@ fn skip_comment(char_queue: &mut VecDeque<char>) -> Result<(), ()> {
                     char_queue.pop_front();
                     loop {
                         // 先頭とその次が "*/"の場合まで1文字ずつ取り出す
-                        if let Some(ch0) = char_queue.get(0) {
+                        if let Some(ch0) = char_queue.front() {
                             if let Some(ch1) = char_queue.get(1) {
                                 if *ch0 == '*' && *ch1 == '/' {
                                     char_queue.pop_front();
@@ -800,14 +1420,14 @@ fn skip_input(char_queue: &mut VecDeque<char>) {
             // コメントが閉じられていない場合
             Err(_) => {
                 // テキスト終端に要求エラーを立てる
-                let tail_pos = PROGRAM_TEXT.get().unwrap().get_tail_pos();
+                let tail_pos = PROGRAM_TEXT.read().unwrap().get_tail_pos();
                 error_exit("parenthes is not closed", tail_pos);
             }
         }
 
         let ch = char_queue.front().unwrap();
 
-        if *ch == ' ' || *ch == '\n' {
+        if *ch == ' ' || *ch == '\n' || *ch == '\t' || *ch == '\r' {
             char_queue.pop_front();
             continue;
         } else {
@@ -816,51 +1436,18 @@ fn skip_input(char_queue: &mut VecDeque<char>) {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum TokenizerState {
-    Global,
-    Local,
-    FucntionDefinition,
-}
-
-struct TokenizerInfo {
-    state: TokenizerState,
-    nest_level: usize,
-}
-
-impl TokenizerInfo {
-    fn new() -> Self {
-        TokenizerInfo {
-            state: TokenizerState::Global,
-            nest_level: 0,
-        }
-    }
-}
-
-// 入力テキストのトークン連結リストを作成する
-pub fn text_tokenizer(text: &str) -> Vec<TokenList> {
+// プリプロセス済みのPROGRAM_TEXTのトークン連結リストを作成する
+// 関数定義やグローバル変数の区切りはAST側で判定する
+pub fn text_tokenizer() -> TokenList {
     // グローバルなPROGRAM_TEXTにミュータブルなcursorを用意して一文字ずつ参照, cursorを移動したいが,
     // グローバル変数はアクセスが面倒なので,
     // スタック内にVecDequeを用意して, トークン化はそれで行う
-    let mut program_char: Vec<char> = text.chars().collect();
-    if !program_char.is_empty() {
-        if let Some(ch) = program_char.get(program_char.len() - 1) {
-            if *ch != '\n' {
-                program_char.push('\n');
-            }
-        }
-    }
-    let program_text = ProgramText::new(program_char);
-    PROGRAM_TEXT.set(program_text).ok();
-    let mut char_queue = VecDeque::from_iter(text.chars());
-    let mut tokenlist_vec: Vec<TokenList> = vec![];
+    let mut char_queue = VecDeque::from_iter(PROGRAM_TEXT.read().unwrap().text.iter().copied());
 
     let mut tokenlist = TokenList::new();
     let mut current_token = &mut tokenlist.head;
 
     let text_len = char_queue.len();
-    let mut local_varibales: Vec<String> = vec![];
-    let mut tokenizer_info = TokenizerInfo::new();
 
     while !char_queue.is_empty() {
         skip_input(&mut char_queue);
@@ -874,79 +1461,52 @@ pub fn text_tokenizer(text: &str) -> Vec<TokenList> {
 
         let ch = char_queue.front().unwrap();
 
-        if tokenizer_info.state == TokenizerState::Global {
-            if ch.is_ascii_alphabetic() {
-                match pop_function_definition_token(&mut char_queue) {
-                    Ok(function_name) => {
-                        new_token.token_kind = TokenKind::FucntionDefinition(function_name);
-                    }
-                    Err(err_string) => {
-                        error_exit(&err_string, token_pos);
-                    }
+        if is_float_literal(&char_queue) {
+            match pop_float(&mut char_queue, token_pos) {
+                Ok((value, ty)) => {
+                    new_token.token_kind = TokenKind::FloatNumber(value.to_bits(), ty);
+                }
+                Err(()) => {
+                    let error_pos = text_len - char_queue.len();
+                    error_exit("unsupported token", error_pos);
                 }
-                // ロカール変数配列初期化
-                // 引数もローカル変数として扱うため, ここで初期化する
-                local_varibales = vec![];
-                tokenizer_info.state = TokenizerState::FucntionDefinition;
-            } else {
-                error_exit(
-                    "only function definition is allowed in global area",
-                    token_pos,
-                );
             }
-        } else if ch.is_digit(10) {
-            match pop_digit(&mut char_queue) {
-                Ok(num) => {
-                    new_token.token_kind = TokenKind::Number(num);
+        } else if ch.is_ascii_digit() {
+            match pop_digit(&mut char_queue, token_pos) {
+                Ok((num, ty)) => {
+                    new_token.token_kind = TokenKind::Number(num, ty);
                 }
                 Err(()) => {
                     let error_pos = text_len - char_queue.len();
                     error_exit("unsupported token", error_pos);
                 }
             }
-        } else if ch.is_ascii_punctuation() {
-            new_token.token_kind = pop_operation(&mut char_queue);
-
-            if new_token.token_kind == TokenKind::Parentheses(ParenthesesKind::RightParentheses) 
-               && tokenizer_info.state == TokenizerState::FucntionDefinition {
-                tokenizer_info.state = TokenizerState::Local;
-            }
-
-            // {}でネストレベルを判定
-            if new_token.token_kind == TokenKind::Braces(BracesKind::LeftBraces) {
-                tokenizer_info.nest_level += 1;
-            } else if new_token.token_kind == TokenKind::Braces(BracesKind::RightBraces) {
-                tokenizer_info.nest_level -= 1;
-                if tokenizer_info.nest_level == 0 {
-                    tokenizer_info.state = TokenizerState::Global;
-                    // tokenlistを更新して
-                    match current_token {
-                        Some(token) => {
-                            token.next = Some(Box::new(new_token));
-                        }
-                        None => {
-                            *current_token = Some(Box::new(new_token));
-                        }
-                    }
-                    tokenlist.local_stack_size = local_varibales.len() * 8;
-                    tokenlist_vec.push(tokenlist);
-                    tokenlist = TokenList::new();
-                    current_token = &mut tokenlist.head;
-                    continue;
+        } else if *ch == '"' {
+            match pop_string_literal(&mut char_queue, token_pos) {
+                Ok(bytes) => {
+                    new_token.token_kind = TokenKind::StringLiteral(bytes);
+                }
+                Err(()) => {
+                    error_exit("string literal is not closed", token_pos);
+                }
+            }
+        } else if *ch == '\'' {
+            match pop_char_literal(&mut char_queue, token_pos) {
+                Ok(num) => {
+                    new_token.token_kind = TokenKind::Number(num, Type::Int);
+                }
+                Err(()) => {
+                    error_exit("invalid character literal", token_pos);
                 }
             }
+        } else if ch.is_ascii_punctuation() {
+            new_token.token_kind = pop_operation(&mut char_queue);
         } else if ch.is_ascii_alphabetic() {
-            new_token.token_kind = pop_identifier_token(&mut char_queue, &mut local_varibales, &mut tokenizer_info);
+            new_token.token_kind = pop_identifier_token(&mut char_queue);
         }
 
         if new_token.token_kind == TokenKind::InvalidToken {
             error_exit("unsupported token", new_token.token_pos);
-        } 
-
-        if let TokenKind::LocalVariableDefinition(_) = new_token.token_kind {
-            if tokenizer_info.state != TokenizerState::FucntionDefinition {
-                continue;
-            }
         }
 
         match current_token {
@@ -959,5 +1519,5 @@ pub fn text_tokenizer(text: &str) -> Vec<TokenList> {
             }
         }
     }
-    tokenlist_vec
+    tokenlist
 }
@@ -0,0 +1,291 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// 変数と式の型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Void,
+    Char,
+    Short,
+    Int,
+    Long,
+    UChar,
+    UShort,
+    UInt,
+    ULong,
+    Float,
+    Double,
+    Pointer(Box<Type>),
+    Array(Box<Type>, usize), // (要素の型, 要素数)
+    Struct(StructRef),
+    Function(Box<Type>, Vec<Type>), // (戻り値の型, 引数の型)
+}
+
+#[derive(Clone)]
+pub struct StructMember {
+    pub name: String,
+    pub ty: Type,
+    pub offset: usize,
+}
+
+// 構造体のメンバとレイアウト
+// membersがNoneの場合はタグだけ宣言された不完全型
+pub struct StructType {
+    members: Option<Vec<StructMember>>,
+    size: usize,
+    align: usize,
+}
+
+// struct node { struct node *next; } のように自身を参照できるよう,
+// 同じタグの型は1つのStructTypeを共有する
+#[derive(Clone)]
+pub struct StructRef(Rc<RefCell<StructType>>);
+
+impl StructRef {
+    pub fn new_incomplete() -> StructRef {
+        StructRef(Rc::new(RefCell::new(StructType {
+            members: None,
+            size: 0,
+            align: 1,
+        })))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.0.borrow().members.is_some()
+    }
+
+    // メンバを宣言順に並べ, それぞれのアラインメントに合わせてオフセットを決める
+    // 構造体全体のサイズは最大のアラインメントの倍数に切り上げる
+    pub fn set_members(&self, members: Vec<(String, Type)>) {
+        let mut offset = 0;
+        let mut align = 1;
+        let mut struct_members = vec![];
+        for (name, ty) in members {
+            offset = align_to(offset, ty.align());
+            align = align.max(ty.align());
+            let size = ty.size();
+            struct_members.push(StructMember { name, ty, offset });
+            offset += size;
+        }
+        let mut struct_type = self.0.borrow_mut();
+        struct_type.size = align_to(offset, align);
+        struct_type.align = align;
+        struct_type.members = Some(struct_members);
+    }
+
+    pub fn find_member(&self, name: &str) -> Option<StructMember> {
+        self.0
+            .borrow()
+            .members
+            .as_ref()?
+            .iter()
+            .find(|member| member.name == name)
+            .cloned()
+    }
+}
+
+// 同じタグから作られた型だけを同じ型とみなす
+impl PartialEq for StructRef {
+    fn eq(&self, other: &StructRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for StructRef {}
+
+// 自己参照する構造体で再帰しないようにメンバは表示しない
+impl fmt::Debug for StructRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "struct")
+    }
+}
+
+impl Type {
+    pub fn pointer_to(ty: Type) -> Type {
+        Type::Pointer(Box::new(ty))
+    }
+
+    pub fn array_of(ty: Type, len: usize) -> Type {
+        Type::Array(Box::new(ty), len)
+    }
+
+    pub fn function(return_ty: Type, arg_types: Vec<Type>) -> Type {
+        Type::Function(Box::new(return_ty), arg_types)
+    }
+
+    // 型のバイト数
+    pub fn size(&self) -> usize {
+        match self {
+            // void *や関数ポインタの演算は1バイト単位で行う
+            Type::Void | Type::Function(_, _) | Type::Char | Type::UChar => 1,
+            Type::Short | Type::UShort => 2,
+            Type::Int | Type::UInt | Type::Float => 4,
+            Type::Long | Type::ULong | Type::Double => 8,
+            Type::Pointer(_) => 8,
+            Type::Array(ty, len) => ty.size() * len,
+            Type::Struct(struct_ref) => struct_ref.0.borrow().size,
+        }
+    }
+
+    // スタック上に置く際のアラインメント
+    pub fn align(&self) -> usize {
+        match self {
+            Type::Array(ty, _) => ty.align(),
+            Type::Struct(struct_ref) => struct_ref.0.borrow().align,
+            _ => self.size(),
+        }
+    }
+
+    // 配列は式中ではポインタとして扱うので, ポインタ演算の対象になる
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, Type::Pointer(_) | Type::Array(_, _))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, Type::Array(_, _))
+    }
+
+    pub fn is_struct(&self) -> bool {
+        matches!(self, Type::Struct(_))
+    }
+
+    pub fn is_function(&self) -> bool {
+        matches!(self, Type::Function(_, _))
+    }
+
+    // 呼び出せる型の場合は関数の型を返す, 関数ポインタは指す先の関数の型
+    pub fn callee_function(&self) -> Option<(&Type, &Vec<Type>)> {
+        match self {
+            Type::Function(return_ty, arg_types) => Some((return_ty, arg_types)),
+            Type::Pointer(ty) => match ty.as_ref() {
+                Type::Function(return_ty, arg_types) => Some((return_ty, arg_types)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Type::Char
+                | Type::Short
+                | Type::Int
+                | Type::Long
+                | Type::UChar
+                | Type::UShort
+                | Type::UInt
+                | Type::ULong
+        )
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::Float | Type::Double)
+    }
+
+    // 四則演算ができる型
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    // 符号なしとして比較, 除算する型, アドレスも符号なしとして扱う
+    pub fn is_unsigned(&self) -> bool {
+        matches!(
+            self,
+            Type::UChar
+                | Type::UShort
+                | Type::UInt
+                | Type::ULong
+                | Type::Pointer(_)
+                | Type::Array(_, _)
+        )
+    }
+
+    // 整数拡張, intより小さい整数型はintとして演算する
+    pub fn promoted(&self) -> Type {
+        if self.is_integer() && self.size() < 4 {
+            Type::Int
+        } else {
+            self.clone()
+        }
+    }
+
+    // 二項演算の両辺を揃える型(通常の算術変換)
+    // 浮動小数点数があればdouble, floatの順に合わせる
+    // 整数同士はサイズの大きい方に合わせ, 同じサイズでは符号なしを優先する
+    pub fn common_type(left: &Type, right: &Type) -> Type {
+        if left.is_pointer() {
+            return Type::ULong;
+        }
+        if right.is_pointer() {
+            return Type::ULong;
+        }
+        if *left == Type::Double || *right == Type::Double {
+            return Type::Double;
+        }
+        if *left == Type::Float || *right == Type::Float {
+            return Type::Float;
+        }
+        let (left, right) = (left.promoted(), right.promoted());
+        if left.size() != right.size() {
+            return if left.size() > right.size() {
+                left
+            } else {
+                right
+            };
+        }
+        if right.is_unsigned() {
+            right
+        } else {
+            left
+        }
+    }
+
+    // 整数の値をこの型に変換する, 型のサイズに切り詰めてから符号に合わせて拡張する
+    pub fn convert_constant(&self, value: i64) -> i64 {
+        match self {
+            Type::Char => value as i8 as i64,
+            Type::UChar => value as u8 as i64,
+            Type::Short => value as i16 as i64,
+            Type::UShort => value as u16 as i64,
+            Type::Int => value as i32 as i64,
+            Type::UInt => value as u32 as i64,
+            _ => value,
+        }
+    }
+
+    // 浮動小数点数の値をこの型でメモリに置く際のビット列
+    // floatは下位32bitに置く
+    pub fn float_bits(&self, value: f64) -> i64 {
+        if *self == Type::Float {
+            (value as f32).to_bits() as i64
+        } else {
+            value.to_bits() as i64
+        }
+    }
+
+    // サイズが決まらない型, 変数やメンバの型には使えない
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            Type::Array(ty, _) => ty.is_incomplete(),
+            Type::Struct(struct_ref) => !struct_ref.is_complete(),
+            Type::Void | Type::Function(_, _) => true,
+            _ => false,
+        }
+    }
+
+    // ポインタの指す先の型, 配列の場合は要素の型
+    pub fn pointee(&self) -> Option<&Type> {
+        match self {
+            Type::Pointer(ty) => Some(ty),
+            Type::Array(ty, _) => Some(ty),
+            _ => None,
+        }
+    }
+}
+
+// nをalignの倍数に切り上げる
+pub fn align_to(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
+}
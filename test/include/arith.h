@@ -0,0 +1,4 @@
+#include "limits_def.h"
+#define SQUARE_OF_TWO 4
+/* 関数の宣言もヘッダに書ける */
+int triple(int x);
@@ -0,0 +1,11 @@
+#ifndef GUARDED_H
+#define GUARDED_H
+// 2回includeしても1回だけ定義される
+int guarded_count;
+#define GUARDED_ADD(x) (guarded_count += (x))
+#if defined(FEATURE_DOUBLE) && FEATURE_DOUBLE >= 2
+#define FEATURE_SCALE 2
+#else
+#define FEATURE_SCALE 1
+#endif
+#endif
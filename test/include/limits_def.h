@@ -0,0 +1,3 @@
+// インクルード元のディレクトリから探される
+#define BUFFER_SIZE 16
+#define LARGE 5000000000L